@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use zville_voxel::file::{self, FileOptions};
+use zville_voxel::math::Vec3;
+use zville_voxel::qb::{ColorFormat, ZAxisOrientation};
+use zville_voxel::schematic::BlockColors;
+use zville_voxel::slices::SliceAxis;
+use zville_voxel::{color_to_rgb_u8, VoxelModel};
+
+const USAGE: &str = "usage:
+    zville-voxel info <input> [options]
+    zville-voxel convert <input> <output> [options]
+    zville-voxel mesh <input> [--obj <output>] [options]
+
+Formats are picked by extension: .qb, .qbt, .qef, .vox, .schem and .schematic. A path without an
+extension is a directory of .png slices.
+
+options:
+    --axis <y|z>      axis that .png slices are stacked along (default y)
+    --compressed      run length encode .qb output
+    --bgra            write .qb colours as BGRA
+    --right-handed    write .qb files with a right handed Z axis
+    --visibility      encode visible sides in .qb output
+    --block-colors <file>
+                      colours for schematic blocks, one `block rrggbb` per line, added to the
+                      built in table. A block of `*` colours every block that is not listed";
+
+fn main() {
+    if let Err(error) = run(env::args().skip(1).collect()) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+struct Args {
+    command: String,
+    paths: Vec<PathBuf>,
+    obj: Option<PathBuf>,
+    options: FileOptions,
+}
+
+fn parse_args(args: Vec<String>) -> anyhow::Result<Args> {
+    let mut args = args.into_iter();
+
+    let command = args
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("missing command\n\n{}", USAGE))?;
+
+    let mut parsed = Args {
+        command,
+        paths: Vec::new(),
+        obj: None,
+        options: FileOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--axis" => {
+                parsed.options.slice_axis = match args.next().as_deref() {
+                    Some("y") => SliceAxis::Y,
+                    Some("z") => SliceAxis::Z,
+                    _ => return Err(anyhow::anyhow!("--axis must be y or z")),
+                }
+            }
+            "--obj" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--obj needs an output path"))?;
+                parsed.obj = Some(PathBuf::from(path));
+            }
+            "--block-colors" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--block-colors needs a path"))?;
+                let text = fs::read_to_string(&path)
+                    .map_err(|error| anyhow::anyhow!("failed to read {}: {}", path, error))?;
+                let colors = BlockColors::parse(&text).map_err(|error| {
+                    anyhow::anyhow!("invalid block colours in {}: {}", path, error)
+                })?;
+
+                parsed.options.block_colors.extend(colors);
+            }
+            "--compressed" => parsed.options.qb_format.compressed = true,
+            "--bgra" => parsed.options.qb_format.color_format = ColorFormat::Bgra,
+            "--right-handed" => {
+                parsed.options.qb_format.z_axis_orientation = ZAxisOrientation::RightHanded
+            }
+            "--visibility" => parsed.options.qb_format.visibility_mask_encoded = true,
+            "-h" | "--help" => return Err(anyhow::anyhow!("{}", USAGE)),
+            _ if arg.starts_with("--") => {
+                return Err(anyhow::anyhow!("unknown option {}\n\n{}", arg, USAGE))
+            }
+            _ => parsed.paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn run(args: Vec<String>) -> anyhow::Result<()> {
+    let args = parse_args(args)?;
+
+    match (args.command.as_str(), args.paths.as_slice()) {
+        ("info", [input]) => info(&read(input, &args.options)?),
+        ("convert", [input, output]) => {
+            let model = read(input, &args.options)?;
+            file::write(output, &model, &args.options)
+                .map_err(|error| anyhow::anyhow!("failed to write {}: {}", output.display(), error))
+        }
+        ("mesh", [input]) => mesh(&read(input, &args.options)?, args.obj.as_deref()),
+        _ => Err(anyhow::anyhow!("{}", USAGE)),
+    }
+}
+
+fn read(path: &Path, options: &FileOptions) -> anyhow::Result<VoxelModel> {
+    file::read(path, options)
+        .map_err(|error| anyhow::anyhow!("failed to read {}: {}", path.display(), error))
+}
+
+fn info(model: &VoxelModel) -> anyhow::Result<()> {
+    println!("model: {}", model.name);
+
+    let mut palette: HashMap<[u8; 3], usize> = HashMap::new();
+
+    for (name, matrix, offset) in model.matrices() {
+        let (size_x, size_y, size_z) = matrix.dimensions();
+
+        println!();
+        println!("part: {}", name);
+        println!("  offset: {} {} {}", offset.x(), offset.y(), offset.z());
+        println!("  dimensions: {}x{}x{}", size_x, size_y, size_z);
+
+        let mut count = 0;
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+
+        for (position, color) in matrix.solid_voxels() {
+            count += 1;
+            min = min.min(position);
+            max = max.max(position);
+            *palette.entry(color_to_rgb_u8(color)).or_default() += 1;
+        }
+
+        println!("  voxels: {}", count);
+
+        if count > 0 {
+            println!(
+                "  bounding box: {} {} {} to {} {} {}",
+                min.x(),
+                min.y(),
+                min.z(),
+                max.x(),
+                max.y(),
+                max.z()
+            );
+        }
+    }
+
+    let mut palette: Vec<_> = palette.into_iter().collect();
+    palette.sort_by(|(a_color, a_count), (b_color, b_count)| {
+        b_count.cmp(a_count).then(a_color.cmp(b_color))
+    });
+
+    println!();
+    println!("palette: {} colours", palette.len());
+    for ([r, g, b], count) in palette {
+        println!("  #{:02x}{:02x}{:02x} {}", r, g, b, count);
+    }
+
+    Ok(())
+}
+
+fn mesh(model: &VoxelModel, obj: Option<&Path>) -> anyhow::Result<()> {
+    let mut writer = match obj {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+
+    let mut total_quads = 0;
+    let mut vertex_count = 0;
+
+    for (name, matrix, offset) in model.matrices() {
+        let quads = matrix.mesh_parts();
+
+        println!(
+            "{}: {} quads, {} triangles",
+            name,
+            quads.len(),
+            quads.len() * 2
+        );
+        total_quads += quads.len();
+
+        if let Some(writer) = writer.as_mut() {
+            writeln!(writer, "o {}", name)?;
+
+            for (quad, color) in quads {
+                let [r, g, b] = color_to_rgb_u8(color);
+
+                for position in quad.positions.iter() {
+                    writeln!(
+                        writer,
+                        "v {} {} {} {} {} {}",
+                        position[0] + offset.x(),
+                        position[1] + offset.y(),
+                        position[2] + offset.z(),
+                        f32::from(r) / 255.0,
+                        f32::from(g) / 255.0,
+                        f32::from(b) / 255.0
+                    )?;
+                }
+
+                for triangle in quad.indices.chunks(3) {
+                    writeln!(
+                        writer,
+                        "f {} {} {}",
+                        vertex_count + triangle[0] as usize + 1,
+                        vertex_count + triangle[1] as usize + 1,
+                        vertex_count + triangle[2] as usize + 1
+                    )?;
+                }
+
+                vertex_count += quad.positions.len();
+            }
+        }
+    }
+
+    println!(
+        "total: {} quads, {} triangles",
+        total_quads,
+        total_quads * 2
+    );
+
+    if let Some(mut writer) = writer {
+        writer.flush()?;
+    }
+
+    Ok(())
+}
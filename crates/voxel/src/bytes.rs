@@ -0,0 +1,175 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// Reads little endian values from a byte slice, keeping track of the offset so that errors can
+/// say where in the file they happened.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// Offset of `bytes` within the file, for readers created by `sub_reader`.
+    base: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            base: 0,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.base + self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.offset)
+    }
+
+    /// Reads `len` bytes as a separate reader that reports offsets relative to the whole file.
+    pub fn sub_reader(&mut self, field: &'static str, len: usize) -> Result<Self, ReadError> {
+        let base = self.offset();
+        let bytes = self.read(field, len)?;
+
+        Ok(Self {
+            bytes,
+            offset: 0,
+            base,
+        })
+    }
+
+    pub fn read_u8(&mut self, field: &'static str) -> Result<u8, ReadError> {
+        Ok(self.read(field, 1)?[0])
+    }
+
+    pub fn read_u32(&mut self, field: &'static str) -> Result<u32, ReadError> {
+        Ok(LittleEndian::read_u32(self.read(field, 4)?))
+    }
+
+    pub fn read_f32(&mut self, field: &'static str) -> Result<f32, ReadError> {
+        Ok(LittleEndian::read_f32(self.read(field, 4)?))
+    }
+
+    pub fn read_i32(&mut self, field: &'static str) -> Result<i32, ReadError> {
+        Ok(LittleEndian::read_i32(self.read(field, 4)?))
+    }
+
+    /// Reads a `u32` that is used as a size or count, failing if it is larger than `max`.
+    pub fn read_len(&mut self, field: &'static str, max: usize) -> Result<usize, ReadError> {
+        let offset = self.offset();
+        let len = self.read_u32(field)?;
+
+        match usize::try_from(len) {
+            Ok(len) if len <= max => Ok(len),
+            _ => Err(ReadError::new(
+                field,
+                offset,
+                ReadErrorKind::TooLarge {
+                    value: u64::from(len),
+                    max,
+                },
+            )),
+        }
+    }
+
+    pub fn read_string(&mut self, field: &'static str, len: usize) -> Result<String, ReadError> {
+        let offset = self.offset();
+        let bytes = self.read(field, len)?;
+
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| ReadError::new(field, offset, ReadErrorKind::InvalidUtf8))
+    }
+
+    pub fn read(&mut self, field: &'static str, len: usize) -> Result<&'a [u8], ReadError> {
+        let remaining = &self.bytes[self.offset.min(self.bytes.len())..];
+
+        if remaining.len() < len {
+            return Err(ReadError::new(
+                field,
+                self.offset(),
+                ReadErrorKind::UnexpectedEof {
+                    expected: len,
+                    remaining: remaining.len(),
+                },
+            ));
+        }
+
+        self.offset += len;
+
+        Ok(&remaining[..len])
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadError {
+    pub field: &'static str,
+    /// The byte the error was found at, or the line for text formats such as `.qef`.
+    pub offset: usize,
+    /// Whether `offset` is a line number, counting from 1, rather than a byte offset.
+    pub is_line: bool,
+    pub kind: ReadErrorKind,
+}
+
+impl ReadError {
+    pub fn new(field: &'static str, offset: usize, kind: ReadErrorKind) -> Self {
+        Self {
+            field,
+            offset,
+            is_line: false,
+            kind,
+        }
+    }
+
+    pub fn on_line(field: &'static str, line: usize, kind: ReadErrorKind) -> Self {
+        Self {
+            field,
+            offset: line,
+            is_line: true,
+            kind,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadErrorKind {
+    UnexpectedEof { expected: usize, remaining: usize },
+    InvalidUtf8,
+    TooLarge { value: u64, max: usize },
+    Invalid(String),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_line {
+            write!(f, "invalid {} on line {}: ", self.field, self.offset)?;
+        } else {
+            write!(f, "invalid {} at byte {}: ", self.field, self.offset)?;
+        }
+
+        match &self.kind {
+            ReadErrorKind::UnexpectedEof {
+                expected,
+                remaining,
+            } => write!(
+                f,
+                "expected {} bytes but only {} remain",
+                expected, remaining
+            ),
+            ReadErrorKind::InvalidUtf8 => write!(f, "not valid UTF-8"),
+            ReadErrorKind::TooLarge { value, max } => {
+                write!(f, "{} is larger than the maximum of {}", value, max)
+            }
+            ReadErrorKind::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for ReadError {}
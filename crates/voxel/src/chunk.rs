@@ -0,0 +1,296 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::{color_to_rgb_u8, Matrix, VisibleFaces, Voxel};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Edge length of the cubes that a world is split into, so that edits only remesh and save the
+/// chunks they touch.
+pub const CHUNK_SIZE: usize = 32;
+
+/// Palette index 0 is reserved for empty voxels so the palette only holds solid ones.
+const EMPTY_INDEX: u32 = 0;
+const MAX_PALETTE_LEN: usize = 64 * 1024;
+
+/// Splits a matrix into chunks of `CHUNK_SIZE`, returning each chunk that holds a solid voxel along
+/// with its offset in voxels. Chunks at the far edges are cropped to the size of the matrix.
+pub fn split(matrix: &Matrix) -> Vec<(Vec3, Matrix)> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+    let mut chunks = Vec::new();
+
+    for chunk_z in (0..size_z).step_by(CHUNK_SIZE) {
+        for chunk_y in (0..size_y).step_by(CHUNK_SIZE) {
+            for chunk_x in (0..size_x).step_by(CHUNK_SIZE) {
+                let mut chunk = Matrix::new(
+                    CHUNK_SIZE.min(size_x - chunk_x),
+                    CHUNK_SIZE.min(size_y - chunk_y),
+                    CHUNK_SIZE.min(size_z - chunk_z),
+                );
+                let offset = Vec3::new(chunk_x as f32, chunk_y as f32, chunk_z as f32);
+                let mut is_empty = true;
+
+                for position in positions(&chunk) {
+                    let source = position + offset;
+                    let voxel = *matrix.lookup(source);
+
+                    if voxel != Voxel::Empty {
+                        is_empty = false;
+                    }
+
+                    chunk.set(position, voxel);
+                    chunk.set_visible_faces(position, matrix.visible_faces(source));
+                }
+
+                if !is_empty {
+                    chunks.push((offset, chunk));
+                }
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Encodes a matrix as a palette of distinct voxels followed by runs of palette indices. Voxels
+/// are run length encoded in storage order, so large areas of ground or air take a few bytes.
+pub fn write<W: Write>(writer: &mut W, matrix: &Matrix) -> io::Result<()> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    let mut palette: Vec<([u8; 3], u8)> = Vec::new();
+    let mut indices: HashMap<([u8; 3], u8), u32> = HashMap::new();
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+
+    for position in positions(matrix) {
+        let index = match matrix.lookup(position) {
+            Voxel::Solid(color) => {
+                let entry = (
+                    color_to_rgb_u8(*color),
+                    matrix.visible_faces(position).bits(),
+                );
+
+                *indices.entry(entry).or_insert_with(|| {
+                    palette.push(entry);
+                    palette.len() as u32
+                })
+            }
+            Voxel::Empty => EMPTY_INDEX,
+        };
+
+        match runs.last_mut() {
+            Some((run_index, len)) if *run_index == index => *len += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+
+    writer.write_u32::<LittleEndian>(size_x as u32)?;
+    writer.write_u32::<LittleEndian>(size_y as u32)?;
+    writer.write_u32::<LittleEndian>(size_z as u32)?;
+
+    writer.write_u32::<LittleEndian>(palette.len() as u32)?;
+    for ([r, g, b], faces) in palette.iter() {
+        writer.write_all(&[*r, *g, *b, *faces])?;
+    }
+
+    writer.write_u32::<LittleEndian>(runs.len() as u32)?;
+    for (index, len) in runs {
+        writer.write_u32::<LittleEndian>(index)?;
+        writer.write_u32::<LittleEndian>(len)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a matrix written by `write`.
+pub fn read(reader: &mut ByteReader) -> Result<Matrix, ReadError> {
+    let size_x = reader.read_len("chunk size x", Matrix::MAX_SIZE)?;
+    let size_y = reader.read_len("chunk size y", Matrix::MAX_SIZE)?;
+    let offset = reader.offset();
+    let size_z = reader.read_len("chunk size z", Matrix::MAX_SIZE)?;
+
+    let volume = size_x * size_y * size_z;
+    if volume > Matrix::MAX_VOLUME {
+        return Err(ReadError::new(
+            "chunk size",
+            offset,
+            ReadErrorKind::TooLarge {
+                value: volume as u64,
+                max: Matrix::MAX_VOLUME,
+            },
+        ));
+    }
+
+    let palette_len = reader.read_len("palette length", MAX_PALETTE_LEN)?;
+    let mut palette = Vec::with_capacity(palette_len);
+
+    for _ in 0..palette_len {
+        let entry = reader.read("palette entry", 4)?;
+        palette.push((
+            Color::rgb_u8(entry[0], entry[1], entry[2]),
+            VisibleFaces::from_bits(entry[3]),
+        ));
+    }
+
+    let mut matrix = Matrix::new(size_x, size_y, size_z);
+    let mut positions = positions(&matrix);
+
+    let run_count = reader.read_len("run count", volume)?;
+
+    for _ in 0..run_count {
+        let offset = reader.offset();
+        let index = reader.read_u32("run palette index")? as usize;
+        let len = reader.read_len("run length", volume)?;
+
+        let entry = match index {
+            0 => None,
+            _ => Some(*palette.get(index - 1).ok_or_else(|| {
+                ReadError::new(
+                    "run palette index",
+                    offset,
+                    ReadErrorKind::Invalid(format!(
+                        "{} is outside of a palette of {} entries",
+                        index, palette_len
+                    )),
+                )
+            })?),
+        };
+
+        for _ in 0..len {
+            let position = positions.next().ok_or_else(|| {
+                ReadError::new(
+                    "run length",
+                    offset,
+                    ReadErrorKind::Invalid("runs hold more voxels than the chunk".to_string()),
+                )
+            })?;
+
+            if let Some((color, faces)) = entry {
+                matrix.set(position, Voxel::Solid(color));
+                matrix.set_visible_faces(position, faces);
+            }
+        }
+    }
+
+    if positions.next().is_some() {
+        return Err(ReadError::new(
+            "runs",
+            reader.offset(),
+            ReadErrorKind::Invalid("runs hold fewer voxels than the chunk".to_string()),
+        ));
+    }
+
+    Ok(matrix)
+}
+
+/// Every position in the matrix in storage order, X first, then Y, then Z.
+fn positions(matrix: &Matrix) -> impl Iterator<Item = Vec3> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    (0..size_z).flat_map(move |z| {
+        (0..size_y)
+            .flat_map(move |y| (0..size_x).map(move |x| Vec3::new(x as f32, y as f32, z as f32)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Matrix {
+        let mut matrix = Matrix::new(40, 3, 33);
+
+        for position in positions(&matrix) {
+            if position.y() == 0.0 {
+                matrix.set(position, Voxel::Solid(Color::rgb_u8(0, 128, 0)));
+            }
+        }
+
+        let tree = Vec3::new(35.0, 1.0, 32.0);
+        matrix.set(tree, Voxel::Solid(Color::rgb_u8(96, 64, 0)));
+        matrix.set_visible_faces(tree, VisibleFaces::TOP);
+
+        matrix
+    }
+
+    fn round_trip(matrix: &Matrix) -> Matrix {
+        let mut bytes = Vec::new();
+        write(&mut bytes, matrix).unwrap();
+
+        let mut reader = ByteReader::new(&bytes);
+        let read_matrix = read(&mut reader).unwrap();
+        assert_eq!(reader.remaining(), 0);
+
+        read_matrix
+    }
+
+    #[test]
+    fn round_trips_chunks() {
+        let matrix = fixture();
+
+        assert_eq!(round_trip(&matrix), matrix);
+        assert_eq!(round_trip(&Matrix::new(2, 1, 1)), Matrix::new(2, 1, 1));
+    }
+
+    #[test]
+    fn splits_into_cropped_chunks_without_empty_ones() {
+        let matrix = fixture();
+        let chunks = split(&matrix);
+
+        let offsets: Vec<_> = chunks.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(
+            offsets,
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(32.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 32.0),
+                Vec3::new(32.0, 0.0, 32.0),
+            ]
+        );
+        assert_eq!(chunks[3].1.dimensions(), (8, 3, 1));
+
+        for (offset, chunk) in chunks.iter() {
+            for position in positions(chunk) {
+                assert_eq!(chunk.lookup(position), matrix.lookup(position + *offset));
+                assert_eq!(
+                    chunk.visible_faces(position),
+                    matrix.visible_faces(position + *offset)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_runs_that_do_not_fill_the_chunk() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &fixture()).unwrap();
+
+        // Changes the length of the last run so the runs fall one voxel short of the chunk, then
+        // one over.
+        let len = bytes.len();
+        let last_run_len = u32::from_le_bytes([
+            bytes[len - 4],
+            bytes[len - 3],
+            bytes[len - 2],
+            bytes[len - 1],
+        ]);
+        bytes[len - 4..].copy_from_slice(&(last_run_len - 1).to_le_bytes());
+
+        assert!(read(&mut ByteReader::new(&bytes)).is_err());
+
+        bytes[len - 4..].copy_from_slice(&(last_run_len + 1).to_le_bytes());
+
+        assert!(read(&mut ByteReader::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn rejects_palette_indices_outside_the_palette() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &Matrix::new(1, 1, 1)).unwrap();
+        // Sizes, an empty palette and a single run of the empty index.
+        assert_eq!(bytes.len(), 4 * 3 + 4 + 4 + 8);
+
+        bytes[20..24].copy_from_slice(&1u32.to_le_bytes());
+
+        assert!(read(&mut ByteReader::new(&bytes)).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::math::Vec3;
+use crate::schematic::{self, BlockColors};
+use crate::slices::{self, SliceAxis};
+use crate::{magica_voxel, model_name, qb, qbt, qef, VoxelModel, VoxelModelPart};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// The voxel file formats that can be read and written outside of the asset server.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FileFormat {
+    QubicleBinary,
+    QubicleBinaryTree,
+    QubicleExchange,
+    MagicaVoxel,
+    Schematic,
+    /// A directory of `.png` images, one per layer.
+    PngSlices,
+}
+
+impl FileFormat {
+    /// Works out the format from the file extension. Paths without an extension are treated as a
+    /// directory of slices.
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let extension = match path.extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => return Ok(FileFormat::PngSlices),
+        };
+
+        match extension.as_str() {
+            "qb" => Ok(FileFormat::QubicleBinary),
+            "qbt" => Ok(FileFormat::QubicleBinaryTree),
+            "qef" => Ok(FileFormat::QubicleExchange),
+            "vox" => Ok(FileFormat::MagicaVoxel),
+            "schem" | "schematic" => Ok(FileFormat::Schematic),
+            _ => Err(anyhow::anyhow!(
+                "unsupported voxel file extension .{}",
+                extension
+            )),
+        }
+    }
+}
+
+/// Settings for formats that can be laid out in more than one way.
+#[derive(Debug, Clone)]
+pub struct FileOptions {
+    pub qb_format: qb::Format,
+    pub slice_axis: SliceAxis,
+    /// The colours given to the blocks of schematics.
+    pub block_colors: BlockColors,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            qb_format: Default::default(),
+            slice_axis: SliceAxis::Y,
+            block_colors: BlockColors::default(),
+        }
+    }
+}
+
+pub fn read(path: &Path, options: &FileOptions) -> anyhow::Result<VoxelModel> {
+    let format = FileFormat::from_path(path)?;
+
+    if format == FileFormat::PngSlices {
+        let name = model_name(path);
+        let matrix = slices::read_dir(path, options.slice_axis)?;
+
+        let mut model = VoxelModel::new(name.clone());
+        model
+            .parts
+            .push(VoxelModelPart::new(name, matrix, Vec3::zero()));
+
+        return Ok(model);
+    }
+
+    let bytes = fs::read(path)?;
+
+    match format {
+        FileFormat::QubicleBinary => Ok(qb::read(path, &bytes)?),
+        FileFormat::QubicleBinaryTree => Ok(qbt::read(path, &bytes)?),
+        FileFormat::QubicleExchange => Ok(qef::read(path, &bytes)?),
+        FileFormat::MagicaVoxel => Ok(magica_voxel::read(path, &bytes)?),
+        FileFormat::Schematic => Ok(schematic::read_model(path, &bytes, &options.block_colors)?),
+        FileFormat::PngSlices => unreachable!(),
+    }
+}
+
+/// Writes a model in the format given by the path. Formats that only hold a single matrix get
+/// every part merged into one.
+pub fn write(path: &Path, model: &VoxelModel, options: &FileOptions) -> anyhow::Result<()> {
+    let format = FileFormat::from_path(path)?;
+
+    match format {
+        FileFormat::QubicleBinary => {
+            let mut writer = create(path)?;
+            qb::write(&mut writer, model, &options.qb_format)?;
+            writer.flush()?;
+        }
+        FileFormat::QubicleExchange => {
+            let mut writer = create(path)?;
+            qef::write(&mut writer, &model.merged_matrix())?;
+            writer.flush()?;
+        }
+        FileFormat::MagicaVoxel => {
+            let mut writer = create(path)?;
+            magica_voxel::write(&mut writer, model)?;
+            writer.flush()?;
+        }
+        FileFormat::PngSlices => {
+            slices::write_dir(&model.merged_matrix(), path, options.slice_axis)?;
+        }
+        FileFormat::QubicleBinaryTree | FileFormat::Schematic => {
+            return Err(anyhow::anyhow!(
+                "writing {:?} files is not supported",
+                format
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn create(path: &Path) -> anyhow::Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path)?))
+}
@@ -0,0 +1,33 @@
+//! Reading, writing and meshing of voxel models. The file formats and mesher have no dependency on
+//! bevy; enabling the `bevy` feature adds asset loaders and a plugin that spawns models as
+//! entities.
+
+mod bytes;
+pub mod chunk;
+pub mod file;
+pub mod magica_voxel;
+pub mod math;
+mod matrix;
+mod mesh;
+mod model;
+mod nbt;
+#[cfg(feature = "bevy")]
+mod plugin;
+pub mod qb;
+pub mod qbt;
+pub mod qef;
+pub mod schematic;
+pub mod slices;
+#[cfg(feature = "bevy")]
+mod spawn;
+mod vox;
+
+pub use bytes::{ByteReader, ReadError, ReadErrorKind};
+pub use matrix::*;
+pub use mesh::*;
+pub use model::*;
+#[cfg(feature = "bevy")]
+pub use plugin::Plugin;
+#[cfg(feature = "bevy")]
+pub use spawn::*;
+pub use vox::*;
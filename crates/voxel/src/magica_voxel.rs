@@ -0,0 +1,957 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::{color_to_rgb_u8, model_name, Matrix, Voxel, VoxelModel, VoxelModelPart};
+#[cfg(feature = "bevy")]
+use bevy::asset::AssetLoader;
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Loads MagicaVoxel `.vox` files. Models are placed using the scene graph when the file has one;
+/// translations are applied but rotations are not yet supported.
+#[cfg(feature = "bevy")]
+#[derive(Default)]
+pub struct MagicaVoxelLoader;
+
+#[cfg(feature = "bevy")]
+impl AssetLoader<VoxelModel> for MagicaVoxelLoader {
+    fn from_bytes(&self, path: &Path, bytes: Vec<u8>) -> anyhow::Result<VoxelModel, anyhow::Error> {
+        Ok(read(path, &bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["vox"];
+        EXTENSIONS
+    }
+}
+
+/// A model as stored in the file, kept in MagicaVoxel's Z up coordinates until the palette is
+/// known.
+struct RawModel {
+    size: [usize; 3],
+    voxels: Vec<[u8; 4]>,
+}
+
+enum Node {
+    Transform {
+        name: Option<String>,
+        translation: [i32; 3],
+        child: i32,
+    },
+    Group {
+        children: Vec<i32>,
+    },
+    Shape {
+        models: Vec<usize>,
+    },
+}
+
+/// Colours indexed by the colour index of a voxel. Index 0 is never used as it means empty.
+type Palette = [[u8; 4]; 256];
+
+const MAX_NODE_DEPTH: usize = 64;
+const MAX_NODE_CHILDREN: usize = 64 * 1024;
+const MAX_DICT_LEN: usize = 1024;
+const MAX_STRING_LEN: usize = 64 * 1024;
+
+/// Reads a MagicaVoxel file into a model with a part for each shape in the scene.
+pub fn read(path: &Path, bytes: &[u8]) -> Result<VoxelModel, ReadError> {
+    let mut reader = ByteReader::new(bytes);
+
+    let magic_offset = reader.offset();
+    if reader.read("magic number", 4)? != b"VOX " {
+        return Err(ReadError::new(
+            "magic number",
+            magic_offset,
+            ReadErrorKind::Invalid("not a MagicaVoxel file".to_string()),
+        ));
+    }
+
+    let _version = reader.read_u32("version")?;
+
+    let (id, _, mut children) = read_chunk(&mut reader)?;
+    if id != "MAIN" {
+        return Err(ReadError::new(
+            "chunk id",
+            magic_offset + 8,
+            ReadErrorKind::Invalid(format!("expected MAIN chunk but found {}", id)),
+        ));
+    }
+
+    let mut models = Vec::new();
+    let mut size = None;
+    let mut palette = default_palette();
+    let mut nodes = HashMap::new();
+
+    while !children.is_empty() {
+        let chunk_offset = children.offset();
+        let (id, mut content, _) = read_chunk(&mut children)?;
+
+        match id.as_str() {
+            "SIZE" => {
+                let model_size = [
+                    content.read_len("model size x", Matrix::MAX_SIZE)?,
+                    content.read_len("model size y", Matrix::MAX_SIZE)?,
+                    content.read_len("model size z", Matrix::MAX_SIZE)?,
+                ];
+
+                let volume = model_size[0] * model_size[1] * model_size[2];
+                if volume > Matrix::MAX_VOLUME {
+                    return Err(ReadError::new(
+                        "model size",
+                        chunk_offset,
+                        ReadErrorKind::TooLarge {
+                            value: volume as u64,
+                            max: Matrix::MAX_VOLUME,
+                        },
+                    ));
+                }
+
+                size = Some(model_size);
+            }
+            "XYZI" => {
+                let size = size.take().ok_or_else(|| {
+                    ReadError::new(
+                        "XYZI chunk",
+                        chunk_offset,
+                        ReadErrorKind::Invalid("not preceded by a SIZE chunk".to_string()),
+                    )
+                })?;
+
+                let count = content.read_len("voxel count", Matrix::MAX_VOLUME)?;
+                let mut voxels = Vec::with_capacity(count);
+
+                for _ in 0..count {
+                    let offset = content.offset();
+                    let voxel = content.read("voxel", 4)?;
+
+                    if (0..3).any(|axis| usize::from(voxel[axis]) >= size[axis]) {
+                        return Err(ReadError::new(
+                            "voxel",
+                            offset,
+                            ReadErrorKind::Invalid("position is outside of the model".to_string()),
+                        ));
+                    }
+
+                    voxels.push([voxel[0], voxel[1], voxel[2], voxel[3]]);
+                }
+
+                models.push(RawModel { size, voxels });
+            }
+            "RGBA" => {
+                // The palette is shifted by one as colour index 0 means empty.
+                for color in palette.iter_mut().skip(1) {
+                    let rgba = content.read("palette colour", 4)?;
+                    color.copy_from_slice(rgba);
+                }
+            }
+            "nTRN" => {
+                let node_id = content.read_i32("node id")?;
+                let attributes = read_dict(&mut content)?;
+                let child = content.read_i32("transform child")?;
+                let _reserved = content.read_i32("transform reserved id")?;
+                let _layer = content.read_i32("transform layer")?;
+                let frame_count = content.read_len("transform frame count", MAX_DICT_LEN)?;
+
+                let mut translation = [0; 3];
+
+                // Only the first frame is used as we do not support animation.
+                for frame in 0..frame_count {
+                    let frame_offset = content.offset();
+                    let attributes = read_dict(&mut content)?;
+
+                    if frame == 0 {
+                        if let Some(value) = attributes.get("_t") {
+                            translation = parse_translation(value).ok_or_else(|| {
+                                ReadError::new(
+                                    "transform translation",
+                                    frame_offset,
+                                    ReadErrorKind::Invalid(format!(
+                                        "{:?} is not a translation",
+                                        value
+                                    )),
+                                )
+                            })?;
+                        }
+                    }
+                }
+
+                nodes.insert(
+                    node_id,
+                    (
+                        chunk_offset,
+                        Node::Transform {
+                            name: attributes.get("_name").cloned(),
+                            translation,
+                            child,
+                        },
+                    ),
+                );
+            }
+            "nGRP" => {
+                let node_id = content.read_i32("node id")?;
+                let _attributes = read_dict(&mut content)?;
+                let count = content.read_len("group child count", MAX_NODE_CHILDREN)?;
+
+                let mut children = Vec::with_capacity(count);
+                for _ in 0..count {
+                    children.push(content.read_i32("group child")?);
+                }
+
+                nodes.insert(node_id, (chunk_offset, Node::Group { children }));
+            }
+            "nSHP" => {
+                let node_id = content.read_i32("node id")?;
+                let _attributes = read_dict(&mut content)?;
+                let count = content.read_len("shape model count", MAX_NODE_CHILDREN)?;
+
+                let mut model_ids = Vec::with_capacity(count);
+                for _ in 0..count {
+                    model_ids.push(content.read_len("shape model id", MAX_NODE_CHILDREN)?);
+                    let _attributes = read_dict(&mut content)?;
+                }
+
+                nodes.insert(node_id, (chunk_offset, Node::Shape { models: model_ids }));
+            }
+            _ => {}
+        }
+    }
+
+    let mut model = VoxelModel::new(model_name(path));
+
+    // Files without a scene graph place every model at the origin.
+    if nodes.is_empty() {
+        for (id, raw) in models.iter().enumerate() {
+            model.parts.push(VoxelModelPart::new(
+                format!("model {}", id),
+                build_matrix(raw, &palette),
+                Vec3::zero(),
+            ));
+        }
+
+        return Ok(model);
+    }
+
+    let scene = Scene {
+        nodes: &nodes,
+        models: &models,
+        palette: &palette,
+    };
+
+    scene.visit(&mut model, 0, 0, [0; 3], None, 0)?;
+
+    Ok(model)
+}
+
+struct Scene<'a> {
+    /// Nodes by id, along with the offset of the chunk they were read from.
+    nodes: &'a HashMap<i32, (usize, Node)>,
+    models: &'a [RawModel],
+    palette: &'a Palette,
+}
+
+impl<'a> Scene<'a> {
+    fn visit(
+        &self,
+        model: &mut VoxelModel,
+        node_id: i32,
+        parent_offset: usize,
+        translation: [i32; 3],
+        name: Option<&str>,
+        depth: usize,
+    ) -> Result<(), ReadError> {
+        if depth > MAX_NODE_DEPTH {
+            return Err(invalid_node(
+                node_id,
+                parent_offset,
+                "scene graph is too deep or has a cycle",
+            ));
+        }
+
+        let (offset, node) = self
+            .nodes
+            .get(&node_id)
+            .ok_or_else(|| invalid_node(node_id, parent_offset, "node does not exist"))?;
+
+        match node {
+            Node::Transform {
+                name: node_name,
+                translation: node_translation,
+                child,
+            } => {
+                let translation = [
+                    translation[0] + node_translation[0],
+                    translation[1] + node_translation[1],
+                    translation[2] + node_translation[2],
+                ];
+
+                let name = node_name.as_deref().or(name);
+
+                self.visit(model, *child, *offset, translation, name, depth + 1)
+            }
+            Node::Group { children } => {
+                for child in children {
+                    self.visit(model, *child, *offset, translation, None, depth + 1)?;
+                }
+
+                Ok(())
+            }
+            Node::Shape { models } => {
+                for id in models {
+                    let raw = self
+                        .models
+                        .get(*id)
+                        .ok_or_else(|| invalid_node(node_id, *offset, "model does not exist"))?;
+
+                    // Translations are to the centre of the model and MagicaVoxel is Z up.
+                    let min = |axis: usize| translation[axis] - (raw.size[axis] / 2) as i32;
+
+                    let mut part = VoxelModelPart::new(
+                        name.map(|name| name.to_string())
+                            .unwrap_or_else(|| format!("model {}", id)),
+                        build_matrix(raw, self.palette),
+                        Vec3::new(min(0) as f32, min(2) as f32, min(1) as f32),
+                    );
+
+                    // The point the transform node positions is the natural pivot of the part.
+                    part.pivot = Vec3::new(
+                        (raw.size[0] / 2) as f32,
+                        (raw.size[2] / 2) as f32,
+                        (raw.size[1] / 2) as f32,
+                    );
+
+                    model.parts.push(part);
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+fn invalid_node(node_id: i32, offset: usize, reason: &str) -> ReadError {
+    ReadError::new(
+        "scene graph",
+        offset,
+        ReadErrorKind::Invalid(format!("node {}: {}", node_id, reason)),
+    )
+}
+
+/// Reads a chunk header, returning the chunk id along with readers for its content and children.
+fn read_chunk<'a>(
+    reader: &mut ByteReader<'a>,
+) -> Result<(String, ByteReader<'a>, ByteReader<'a>), ReadError> {
+    let id = String::from_utf8_lossy(reader.read("chunk id", 4)?).into_owned();
+    let content_len = reader.read_len("chunk content size", usize::MAX)?;
+    let children_len = reader.read_len("chunk children size", usize::MAX)?;
+
+    let content = reader.sub_reader("chunk content", content_len)?;
+    let children = reader.sub_reader("chunk children", children_len)?;
+
+    Ok((id, content, children))
+}
+
+fn read_dict(reader: &mut ByteReader) -> Result<HashMap<String, String>, ReadError> {
+    let len = reader.read_len("dictionary size", MAX_DICT_LEN)?;
+    let mut dict = HashMap::with_capacity(len);
+
+    for _ in 0..len {
+        let key_len = reader.read_len("dictionary key length", MAX_STRING_LEN)?;
+        let key = reader.read_string("dictionary key", key_len)?;
+        let value_len = reader.read_len("dictionary value length", MAX_STRING_LEN)?;
+        let value = reader.read_string("dictionary value", value_len)?;
+
+        dict.insert(key, value);
+    }
+
+    Ok(dict)
+}
+
+fn parse_translation(value: &str) -> Option<[i32; 3]> {
+    let mut parts = value.split_whitespace().map(|part| part.parse().ok());
+
+    Some([parts.next()??, parts.next()??, parts.next()??])
+}
+
+/// Converts a MagicaVoxel model, which is Z up, into a Y up matrix.
+fn build_matrix(raw: &RawModel, palette: &Palette) -> Matrix {
+    let mut matrix = Matrix::new(raw.size[0], raw.size[2], raw.size[1]);
+
+    for [x, y, z, index] in raw.voxels.iter() {
+        let [r, g, b, _] = palette[usize::from(*index)];
+
+        matrix.set(
+            Vec3::new(f32::from(*x), f32::from(*z), f32::from(*y)),
+            Voxel::Solid(Color::rgb_u8(r, g, b)),
+        );
+    }
+
+    matrix
+}
+
+/// MagicaVoxel uses this palette for files that do not have an RGBA chunk. It is a 6x6x6 colour
+/// cube, without black, followed by red, green, blue and grey ramps.
+fn default_palette() -> Palette {
+    const CUBE_STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP_STEPS: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+
+    for r in CUBE_STEPS.iter() {
+        for g in CUBE_STEPS.iter() {
+            for b in CUBE_STEPS.iter() {
+                if index < 216 {
+                    palette[index] = [*r, *g, *b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    for channel in 0..4 {
+        for step in RAMP_STEPS.iter() {
+            palette[index] = match channel {
+                0 => [*step, 0, 0, 0xff],
+                1 => [0, *step, 0, 0xff],
+                2 => [0, 0, *step, 0xff],
+                _ => [*step, *step, *step, 0xff],
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+const VERSION: u32 = 150;
+/// MagicaVoxel stores positions as bytes so models can be at most this large along each axis.
+const MAX_MODEL_SIZE: usize = 256;
+/// Colour index 0 means empty so only 255 colours can be used.
+const MAX_PALETTE_COLORS: usize = 255;
+
+/// Encodes a model as a MagicaVoxel file, with a transform node per part so that the parts keep
+/// their names and offsets.
+pub fn write<W: Write>(writer: &mut W, model: &VoxelModel) -> io::Result<()> {
+    write_matrices(writer, &model.matrices())
+}
+
+/// Encodes a single matrix as a MagicaVoxel file.
+pub fn write_matrix<W: Write>(writer: &mut W, matrix: &Matrix) -> io::Result<()> {
+    write_matrices(writer, &[("", matrix, Vec3::zero())])
+}
+
+fn write_matrices<W: Write>(writer: &mut W, matrices: &[(&str, &Matrix, Vec3)]) -> io::Result<()> {
+    for (name, matrix, _) in matrices {
+        let (size_x, size_y, size_z) = matrix.dimensions();
+
+        if size_x > MAX_MODEL_SIZE || size_y > MAX_MODEL_SIZE || size_z > MAX_MODEL_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "matrix {:?} is {}x{}x{} but MagicaVoxel models can be at most {} along each axis",
+                    name, size_x, size_y, size_z, MAX_MODEL_SIZE
+                ),
+            ));
+        }
+    }
+
+    let palette = PaletteBuilder::new(matrices.iter().map(|(_, matrix, _)| *matrix));
+
+    let mut children = Vec::new();
+
+    for (_, matrix, _) in matrices {
+        let (size_x, size_y, size_z) = matrix.dimensions();
+
+        // MagicaVoxel is Z up so the Y and Z axes are swapped.
+        let mut size = Vec::new();
+        size.write_u32::<LittleEndian>(size_x as u32)?;
+        size.write_u32::<LittleEndian>(size_z as u32)?;
+        size.write_u32::<LittleEndian>(size_y as u32)?;
+        write_chunk(&mut children, b"SIZE", &size)?;
+
+        let mut voxels = Vec::new();
+        let mut count = 0u32;
+
+        for z in 0..size_z {
+            for y in 0..size_y {
+                for x in 0..size_x {
+                    if let Voxel::Solid(color) =
+                        matrix.lookup(Vec3::new(x as f32, y as f32, z as f32))
+                    {
+                        voxels.extend_from_slice(&[
+                            x as u8,
+                            z as u8,
+                            y as u8,
+                            palette.index(color_to_rgb_u8(*color)),
+                        ]);
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        let mut xyzi = Vec::new();
+        xyzi.write_u32::<LittleEndian>(count)?;
+        xyzi.extend_from_slice(&voxels);
+        write_chunk(&mut children, b"XYZI", &xyzi)?;
+    }
+
+    write_scene(&mut children, matrices)?;
+
+    let mut rgba = Vec::new();
+    for index in 0..256 {
+        let [r, g, b] = palette.colors.get(index).copied().unwrap_or([0, 0, 0]);
+        rgba.extend_from_slice(&[r, g, b, 0xff]);
+    }
+    write_chunk(&mut children, b"RGBA", &rgba)?;
+
+    writer.write_all(b"VOX ")?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_all(b"MAIN")?;
+    writer.write_u32::<LittleEndian>(0)?;
+    writer.write_u32::<LittleEndian>(children.len() as u32)?;
+    writer.write_all(&children)
+}
+
+/// Writes a root transform and group with a transform and shape node for each model.
+fn write_scene(out: &mut Vec<u8>, matrices: &[(&str, &Matrix, Vec3)]) -> io::Result<()> {
+    write_transform_node(out, 0, 1, &[], "0 0 0")?;
+
+    let mut group = Vec::new();
+    group.write_i32::<LittleEndian>(1)?;
+    write_dict(&mut group, &[])?;
+    group.write_u32::<LittleEndian>(matrices.len() as u32)?;
+    for index in 0..matrices.len() {
+        group.write_i32::<LittleEndian>(2 + index as i32 * 2)?;
+    }
+    write_chunk(out, b"nGRP", &group)?;
+
+    for (index, (name, matrix, offset)) in matrices.iter().enumerate() {
+        let (size_x, size_y, size_z) = matrix.dimensions();
+        let node_id = 2 + index as i32 * 2;
+
+        // Translations are to the centre of the model.
+        let translation = format!(
+            "{} {} {}",
+            offset.x() as i32 + (size_x / 2) as i32,
+            offset.z() as i32 + (size_z / 2) as i32,
+            offset.y() as i32 + (size_y / 2) as i32,
+        );
+
+        let mut attributes = Vec::new();
+        if !name.is_empty() {
+            attributes.push(("_name", *name));
+        }
+
+        write_transform_node(out, node_id, node_id + 1, &attributes, &translation)?;
+
+        let mut shape = Vec::new();
+        shape.write_i32::<LittleEndian>(node_id + 1)?;
+        write_dict(&mut shape, &[])?;
+        shape.write_u32::<LittleEndian>(1)?;
+        shape.write_u32::<LittleEndian>(index as u32)?;
+        write_dict(&mut shape, &[])?;
+        write_chunk(out, b"nSHP", &shape)?;
+    }
+
+    Ok(())
+}
+
+fn write_transform_node(
+    out: &mut Vec<u8>,
+    node_id: i32,
+    child: i32,
+    attributes: &[(&str, &str)],
+    translation: &str,
+) -> io::Result<()> {
+    let mut transform = Vec::new();
+    transform.write_i32::<LittleEndian>(node_id)?;
+    write_dict(&mut transform, attributes)?;
+    transform.write_i32::<LittleEndian>(child)?;
+    transform.write_i32::<LittleEndian>(-1)?;
+    transform.write_i32::<LittleEndian>(0)?;
+    transform.write_u32::<LittleEndian>(1)?;
+    write_dict(&mut transform, &[("_t", translation)])?;
+
+    write_chunk(out, b"nTRN", &transform)
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) -> io::Result<()> {
+    out.write_all(id)?;
+    out.write_u32::<LittleEndian>(content.len() as u32)?;
+    out.write_u32::<LittleEndian>(0)?;
+    out.write_all(content)
+}
+
+fn write_dict(out: &mut Vec<u8>, dict: &[(&str, &str)]) -> io::Result<()> {
+    out.write_u32::<LittleEndian>(dict.len() as u32)?;
+
+    for (key, value) in dict {
+        out.write_u32::<LittleEndian>(key.len() as u32)?;
+        out.write_all(key.as_bytes())?;
+        out.write_u32::<LittleEndian>(value.len() as u32)?;
+        out.write_all(value.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Builds a palette for the colours used by a set of matrices. When there are more colours than
+/// fit in a palette they are quantised using median cut and each colour is mapped to the nearest
+/// palette entry.
+struct PaletteBuilder {
+    colors: Vec<[u8; 3]>,
+    indices: HashMap<[u8; 3], u8>,
+}
+
+impl PaletteBuilder {
+    fn new<'a>(matrices: impl Iterator<Item = &'a Matrix>) -> Self {
+        let mut unique = BTreeSet::new();
+
+        for matrix in matrices {
+            let (size_x, size_y, size_z) = matrix.dimensions();
+
+            for z in 0..size_z {
+                for y in 0..size_y {
+                    for x in 0..size_x {
+                        if let Voxel::Solid(color) =
+                            matrix.lookup(Vec3::new(x as f32, y as f32, z as f32))
+                        {
+                            unique.insert(color_to_rgb_u8(*color));
+                        }
+                    }
+                }
+            }
+        }
+
+        let unique: Vec<[u8; 3]> = unique.into_iter().collect();
+
+        let colors = if unique.len() <= MAX_PALETTE_COLORS {
+            unique.clone()
+        } else {
+            median_cut(unique.clone(), MAX_PALETTE_COLORS)
+        };
+
+        let indices = unique
+            .into_iter()
+            .map(|color| (color, nearest(&colors, color)))
+            .collect();
+
+        Self { colors, indices }
+    }
+
+    /// The colour index for a colour, which is one more than its position in the palette.
+    fn index(&self, color: [u8; 3]) -> u8 {
+        self.indices[&color] + 1
+    }
+}
+
+fn median_cut(colors: Vec<[u8; 3]>, max: usize) -> Vec<[u8; 3]> {
+    let mut boxes = vec![colors];
+
+    while boxes.len() < max {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (index, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range);
+
+        let (index, channel) = match widest {
+            Some((index, channel, range)) if range > 0 => (index, channel),
+            _ => break,
+        };
+
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|color| color[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .map(|colors| {
+            let mut sum = [0usize; 3];
+            for color in colors {
+                for channel in 0..3 {
+                    sum[channel] += usize::from(color[channel]);
+                }
+            }
+
+            let average = |channel: usize| (sum[channel] / colors.len()) as u8;
+
+            [average(0), average(1), average(2)]
+        })
+        .collect()
+}
+
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|color| color[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|color| color[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |other: &[u8; 3]| -> u32 {
+        (0..3)
+            .map(|channel| {
+                let delta = i32::from(color[channel]) - i32::from(other[channel]);
+                (delta * delta) as u32
+            })
+            .sum()
+    };
+
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, other)| distance(other))
+        .map(|(index, _)| index as u8)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(r: u8, g: u8, b: u8) -> Voxel {
+        Voxel::Solid(Color::rgb_u8(r, g, b))
+    }
+
+    fn write_to_vec(model: &VoxelModel) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, model).unwrap();
+        bytes
+    }
+
+    /// Starts a file with a MAIN chunk holding `children`.
+    fn file(children: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.write_u32::<LittleEndian>(VERSION).unwrap();
+        bytes.extend_from_slice(b"MAIN");
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        bytes
+            .write_u32::<LittleEndian>(children.len() as u32)
+            .unwrap();
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    /// Writes SIZE and XYZI chunks for a model in MagicaVoxel's Z up coordinates.
+    fn write_model(out: &mut Vec<u8>, size: [u32; 3], voxels: &[[u8; 4]]) {
+        let mut content = Vec::new();
+        for value in size.iter() {
+            content.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        write_chunk(out, b"SIZE", &content).unwrap();
+
+        let mut content = Vec::new();
+        content
+            .write_u32::<LittleEndian>(voxels.len() as u32)
+            .unwrap();
+        for voxel in voxels.iter() {
+            content.extend_from_slice(voxel);
+        }
+        write_chunk(out, b"XYZI", &content).unwrap();
+    }
+
+    fn write_group(out: &mut Vec<u8>, node_id: i32, children: &[i32]) {
+        let mut content = Vec::new();
+        content.write_i32::<LittleEndian>(node_id).unwrap();
+        write_dict(&mut content, &[]).unwrap();
+        content
+            .write_u32::<LittleEndian>(children.len() as u32)
+            .unwrap();
+        for child in children.iter() {
+            content.write_i32::<LittleEndian>(*child).unwrap();
+        }
+        write_chunk(out, b"nGRP", &content).unwrap();
+    }
+
+    fn write_shape(out: &mut Vec<u8>, node_id: i32, model: u32) {
+        let mut content = Vec::new();
+        content.write_i32::<LittleEndian>(node_id).unwrap();
+        write_dict(&mut content, &[]).unwrap();
+        content.write_u32::<LittleEndian>(1).unwrap();
+        content.write_u32::<LittleEndian>(model).unwrap();
+        write_dict(&mut content, &[]).unwrap();
+        write_chunk(out, b"nSHP", &content).unwrap();
+    }
+
+    #[test]
+    fn round_trips_parts() {
+        let mut house = Matrix::new(3, 4, 5);
+        house.set(Vec3::new(0.0, 0.0, 0.0), solid(255, 0, 0));
+        house.set(Vec3::new(2.0, 3.0, 4.0), solid(0, 255, 0));
+        house.set(Vec3::new(1.0, 2.0, 0.0), solid(10, 20, 30));
+
+        let mut tree = Matrix::new(2, 6, 2);
+        for y in 0..6 {
+            tree.set(Vec3::new(1.0, y as f32, 0.0), solid(0, 96, 0));
+        }
+
+        let mut model = VoxelModel::new("town".to_string());
+        model.parts.push(VoxelModelPart::new(
+            "house".to_string(),
+            house,
+            Vec3::new(4.0, 0.0, -7.0),
+        ));
+        model.parts.push(VoxelModelPart::new(
+            "tree".to_string(),
+            tree,
+            Vec3::new(-3.0, 1.0, 2.0),
+        ));
+
+        let read_model = read(Path::new("town.vox"), &write_to_vec(&model)).unwrap();
+
+        assert_eq!(read_model.name, "town");
+        assert_eq!(read_model.parts.len(), 2);
+
+        for (read_part, part) in read_model.parts.iter().zip(model.parts.iter()) {
+            assert_eq!(read_part.name, part.name);
+            assert_eq!(read_part.offset, part.offset);
+            assert_eq!(read_part.matrix, part.matrix);
+        }
+    }
+
+    #[test]
+    fn reads_scene_graphs() {
+        let mut children = Vec::new();
+        write_model(&mut children, [2, 3, 4], &[[1, 0, 3, 1], [0, 2, 0, 216]]);
+        write_model(&mut children, [1, 1, 1], &[[0, 0, 0, 255]]);
+
+        // The root transform holds a group with a named model and a model nested in a second
+        // group, where translations add up on the way down.
+        write_transform_node(&mut children, 0, 1, &[], "0 0 0").unwrap();
+        write_group(&mut children, 1, &[2, 4]);
+        write_transform_node(&mut children, 2, 3, &[("_name", "base")], "10 0 2").unwrap();
+        write_shape(&mut children, 3, 0);
+        write_transform_node(&mut children, 4, 5, &[], "0 0 5").unwrap();
+        write_group(&mut children, 5, &[6]);
+        write_transform_node(&mut children, 6, 7, &[], "1 2 3").unwrap();
+        write_shape(&mut children, 7, 1);
+
+        let model = read(Path::new("scene.vox"), &file(&children)).unwrap();
+
+        assert_eq!(model.parts.len(), 2);
+
+        // Translations are to the centre of the model, and MagicaVoxel's Y and Z are swapped.
+        let base = &model.parts[0];
+        assert_eq!(base.name, "base");
+        assert_eq!(base.offset, Vec3::new(9.0, 0.0, -1.0));
+        assert_eq!(base.pivot, Vec3::new(1.0, 2.0, 1.0));
+        assert_eq!(base.matrix.dimensions(), (2, 4, 3));
+
+        // Without an RGBA chunk colours come from the default palette, where index 216 starts the
+        // red ramp.
+        assert_eq!(
+            *base.matrix.lookup(Vec3::new(1.0, 3.0, 0.0)),
+            solid(255, 255, 255)
+        );
+        assert_eq!(
+            *base.matrix.lookup(Vec3::new(0.0, 0.0, 2.0)),
+            solid(0xee, 0, 0)
+        );
+        assert_eq!(base.matrix.solid_voxels().count(), 2);
+
+        let nested = &model.parts[1];
+        assert_eq!(nested.name, "model 1");
+        assert_eq!(nested.offset, Vec3::new(1.0, 8.0, 2.0));
+        assert_eq!(*nested.matrix.lookup(Vec3::zero()), solid(0x11, 0x11, 0x11));
+    }
+
+    #[test]
+    fn places_models_at_the_origin_without_a_scene_graph() {
+        let mut children = Vec::new();
+        write_model(&mut children, [1, 2, 3], &[[0, 1, 2, 1]]);
+
+        let model = read(Path::new("plain.vox"), &file(&children)).unwrap();
+
+        assert_eq!(model.parts.len(), 1);
+        assert_eq!(model.parts[0].offset, Vec3::zero());
+        assert_eq!(
+            *model.parts[0].matrix.lookup(Vec3::new(0.0, 2.0, 1.0)),
+            solid(255, 255, 255)
+        );
+    }
+
+    #[test]
+    fn rejects_scene_graph_cycles() {
+        let mut children = Vec::new();
+        write_model(&mut children, [1, 1, 1], &[]);
+        write_transform_node(&mut children, 0, 1, &[], "0 0 0").unwrap();
+        write_group(&mut children, 1, &[0]);
+
+        assert!(read(Path::new("cycle.vox"), &file(&children)).is_err());
+    }
+
+    #[test]
+    fn rejects_voxels_outside_the_model() {
+        let mut children = Vec::new();
+        write_model(&mut children, [1, 1, 1], &[[0, 1, 0, 1]]);
+
+        assert!(read(Path::new("outside.vox"), &file(&children)).is_err());
+    }
+
+    #[test]
+    fn quantises_more_than_255_colours() {
+        // 512 distinct colours, one per voxel.
+        let mut matrix = Matrix::new(8, 8, 8);
+        let channel = |value: usize| (value * 36) as u8;
+        for z in 0..8 {
+            for y in 0..8 {
+                for x in 0..8 {
+                    matrix.set(
+                        Vec3::new(x as f32, y as f32, z as f32),
+                        solid(channel(x), channel(y), channel(z)),
+                    );
+                }
+            }
+        }
+
+        let mut bytes = Vec::new();
+        write_matrix(&mut bytes, &matrix).unwrap();
+        let model = read(Path::new("colours.vox"), &bytes).unwrap();
+        let read_matrix = &model.parts[0].matrix;
+
+        let mut colors = BTreeSet::new();
+
+        for (position, color) in matrix.solid_voxels() {
+            let read_color = match read_matrix.lookup(position) {
+                Voxel::Solid(color) => color_to_rgb_u8(*color),
+                Voxel::Empty => panic!("voxel at {:?} is empty", position),
+            };
+            colors.insert(read_color);
+
+            // Quantising only moves a colour by up to one step of 36 on each channel.
+            let expected = color_to_rgb_u8(color);
+            for channel in 0..3 {
+                let delta = i32::from(read_color[channel]) - i32::from(expected[channel]);
+                assert!(delta.abs() <= 36, "{:?} became {:?}", expected, read_color);
+            }
+        }
+
+        assert!(colors.len() <= MAX_PALETTE_COLORS);
+        assert!(colors.len() > 200);
+    }
+
+    #[test]
+    fn median_cut_averages_each_box() {
+        let colors = vec![[0, 0, 0], [10, 0, 0], [200, 0, 0], [210, 0, 0]];
+
+        let mut palette = median_cut(colors, 2);
+        palette.sort_unstable();
+
+        assert_eq!(palette, vec![[5, 0, 0], [205, 0, 0]]);
+    }
+}
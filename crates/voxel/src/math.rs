@@ -0,0 +1,160 @@
+//! The vector and colour types used throughout the crate. With the `bevy` feature enabled these are
+//! bevy's own types so models can be handed straight to the engine; without it they are minimal
+//! stand-ins exposing the same methods.
+
+#[cfg(feature = "bevy")]
+pub use bevy::math::Vec3;
+#[cfg(feature = "bevy")]
+pub use bevy::render::color::Color;
+
+#[cfg(not(feature = "bevy"))]
+pub use self::standalone::{Color, Vec3};
+
+#[cfg(not(feature = "bevy"))]
+mod standalone {
+    use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub, SubAssign};
+
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    pub struct Vec3([f32; 3]);
+
+    impl Vec3 {
+        pub fn new(x: f32, y: f32, z: f32) -> Self {
+            Self([x, y, z])
+        }
+
+        pub fn zero() -> Self {
+            Self([0.0; 3])
+        }
+
+        pub fn splat(v: f32) -> Self {
+            Self([v; 3])
+        }
+
+        pub fn x(self) -> f32 {
+            self.0[0]
+        }
+
+        pub fn y(self) -> f32 {
+            self.0[1]
+        }
+
+        pub fn z(self) -> f32 {
+            self.0[2]
+        }
+
+        pub fn set_x(&mut self, x: f32) {
+            self.0[0] = x;
+        }
+
+        pub fn set_y(&mut self, y: f32) {
+            self.0[1] = y;
+        }
+
+        pub fn set_z(&mut self, z: f32) {
+            self.0[2] = z;
+        }
+
+        pub fn min(self, other: Self) -> Self {
+            Self::new(
+                self.x().min(other.x()),
+                self.y().min(other.y()),
+                self.z().min(other.z()),
+            )
+        }
+
+        pub fn max(self, other: Self) -> Self {
+            Self::new(
+                self.x().max(other.x()),
+                self.y().max(other.y()),
+                self.z().max(other.z()),
+            )
+        }
+    }
+
+    impl Add for Vec3 {
+        type Output = Self;
+
+        fn add(self, other: Self) -> Self {
+            Self::new(
+                self.x() + other.x(),
+                self.y() + other.y(),
+                self.z() + other.z(),
+            )
+        }
+    }
+
+    impl AddAssign for Vec3 {
+        fn add_assign(&mut self, other: Self) {
+            *self = *self + other;
+        }
+    }
+
+    impl Sub for Vec3 {
+        type Output = Self;
+
+        fn sub(self, other: Self) -> Self {
+            Self::new(
+                self.x() - other.x(),
+                self.y() - other.y(),
+                self.z() - other.z(),
+            )
+        }
+    }
+
+    impl SubAssign for Vec3 {
+        fn sub_assign(&mut self, other: Self) {
+            *self = *self - other;
+        }
+    }
+
+    impl Mul<f32> for Vec3 {
+        type Output = Self;
+
+        fn mul(self, scale: f32) -> Self {
+            Self::new(self.x() * scale, self.y() * scale, self.z() * scale)
+        }
+    }
+
+    impl Index<usize> for Vec3 {
+        type Output = f32;
+
+        fn index(&self, index: usize) -> &f32 {
+            &self.0[index]
+        }
+    }
+
+    impl IndexMut<usize> for Vec3 {
+        fn index_mut(&mut self, index: usize) -> &mut f32 {
+            &mut self.0[index]
+        }
+    }
+
+    impl From<Vec3> for [f32; 3] {
+        fn from(v: Vec3) -> Self {
+            v.0
+        }
+    }
+
+    /// A linear RGBA colour with channels in the range 0 to 1.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Color {
+        pub r: f32,
+        pub g: f32,
+        pub b: f32,
+        pub a: f32,
+    }
+
+    impl Color {
+        pub fn rgb(r: f32, g: f32, b: f32) -> Self {
+            Self::rgba(r, g, b, 1.0)
+        }
+
+        pub fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+            Self { r, g, b, a }
+        }
+
+        pub fn rgb_u8(r: u8, g: u8, b: u8) -> Self {
+            Self::rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+        }
+    }
+}
@@ -1,8 +1,7 @@
-use crate::voxel::Voxel;
-use bevy::prelude::*;
-use bevy::render::{mesh::VertexAttribute, pipeline::PrimitiveTopology};
+use crate::math::{Color, Vec3};
+use crate::{MeshBuffer, VisibleFaces, Voxel};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Size {
     x: usize,
     y: usize,
@@ -19,13 +18,20 @@ impl Size {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Matrix {
     size: Size,
     voxels: Vec<Voxel>,
+    faces: Vec<VisibleFaces>,
 }
 
 impl Matrix {
+    /// The largest size along any axis that will be read from a file.
+    pub const MAX_SIZE: usize = 1024;
+    /// The largest number of voxels that will be read from a file, to avoid running out of memory
+    /// on corrupt or malicious assets.
+    pub const MAX_VOLUME: usize = 256 * 256 * 256;
+
     pub fn new(size_x: usize, size_y: usize, size_z: usize) -> Self {
         let size = Size::new(size_x, size_y, size_z);
         let capacity = size.volume();
@@ -33,9 +39,18 @@ impl Matrix {
         Self {
             size,
             voxels: vec![Voxel::Empty; capacity],
+            faces: vec![VisibleFaces::ALL; capacity],
         }
     }
 
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        (self.size.x, self.size.y, self.size.z)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.volume() == 0
+    }
+
     pub fn set(&mut self, pos: Vec3, v: Voxel) {
         let index = self.index(pos);
         self.voxels[index] = v;
@@ -46,6 +61,37 @@ impl Matrix {
         &self.voxels[index]
     }
 
+    /// The position and colour of every solid voxel.
+    pub fn solid_voxels(&self) -> impl Iterator<Item = (Vec3, Color)> + '_ {
+        let (size_x, size_y, _) = self.dimensions();
+
+        self.voxels
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, voxel)| match voxel {
+                Voxel::Solid(color) => {
+                    let x = index % size_x;
+                    let y = (index / size_x) % size_y;
+                    let z = index / (size_x * size_y);
+
+                    Some((Vec3::new(x as f32, y as f32, z as f32), *color))
+                }
+                Voxel::Empty => None,
+            })
+    }
+
+    /// Restricts which sides of the voxel at the given position are meshed. All sides are visible
+    /// by default; hidden sides are useful where a face is known to be covered.
+    pub fn set_visible_faces(&mut self, pos: Vec3, faces: VisibleFaces) {
+        let index = self.index(pos);
+        self.faces[index] = faces;
+    }
+
+    pub fn visible_faces(&self, pos: Vec3) -> VisibleFaces {
+        let index = self.index(pos);
+        self.faces[index]
+    }
+
     fn index(&self, pos: Vec3) -> usize {
         let size_x = self.size.x as f32;
         let size_y = self.size.y as f32;
@@ -54,8 +100,8 @@ impl Matrix {
         index as usize
     }
 
-    pub fn mesh_parts(&self) -> Vec<(Mesh, Color)> {
-        let mut parts: Vec<(Mesh, Color)> = Vec::new();
+    pub fn mesh_parts(&self) -> Vec<(MeshBuffer, Color)> {
+        let mut parts: Vec<(MeshBuffer, Color)> = Vec::new();
 
         let dimensions = [self.size.x, self.size.y, self.size.z];
 
@@ -80,7 +126,7 @@ impl Matrix {
                 Back,
                 Right,
                 Left,
-            };
+            }
 
             let side = match direction {
                 0 => {
@@ -107,6 +153,15 @@ impl Matrix {
                 _ => panic!("should not reach this"),
             };
 
+            let side_faces = match side {
+                Side::Top => VisibleFaces::TOP,
+                Side::Bottom => VisibleFaces::BOTTOM,
+                Side::Right => VisibleFaces::RIGHT,
+                Side::Left => VisibleFaces::LEFT,
+                Side::Front => VisibleFaces::FRONT,
+                Side::Back => VisibleFaces::BACK,
+            };
+
             // Iterate over the matrix layer by layer.
             start_pos[direction] = -1.0;
             while start_pos[direction] < dimensions[direction] as f32 {
@@ -134,9 +189,12 @@ impl Matrix {
                         {
                             None
                         } else if is_back_face {
-                            voxel_b
+                            voxel_b.filter(|_| {
+                                self.visible_faces(start_pos + axis_offset)
+                                    .contains(side_faces)
+                            })
                         } else {
-                            voxel_a
+                            voxel_a.filter(|_| self.visible_faces(start_pos).contains(side_faces))
                         };
 
                         n += 1;
@@ -215,25 +273,16 @@ impl Matrix {
                                     vec![2, 3, 1, 1, 0, 2]
                                 };
 
-                                let mut positions = Vec::new();
-                                let mut normals = Vec::new();
-                                let mut uvs = Vec::new();
+                                let mut m = MeshBuffer {
+                                    indices,
+                                    ..Default::default()
+                                };
                                 for (position, normal, uv) in vertices.iter() {
-                                    positions.push(*position);
-                                    normals.push(*normal);
-                                    uvs.push(*uv);
+                                    m.positions.push(*position);
+                                    m.normals.push(*normal);
+                                    m.uvs.push(*uv);
                                 }
 
-                                let m = Mesh {
-                                    primitive_topology: PrimitiveTopology::TriangleList,
-                                    attributes: vec![
-                                        VertexAttribute::position(positions),
-                                        VertexAttribute::normal(normals),
-                                        VertexAttribute::uv(uvs),
-                                    ],
-                                    indices: Some(indices),
-                                };
-
                                 parts.push((m, *color));
                             }
 
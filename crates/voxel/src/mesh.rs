@@ -0,0 +1,26 @@
+/// Vertex data produced by meshing a `Matrix`, as an indexed triangle list. Kept independent of
+/// any renderer so that tools can use it without pulling in bevy.
+#[derive(Debug, Clone, Default)]
+pub struct MeshBuffer {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+#[cfg(feature = "bevy")]
+impl From<MeshBuffer> for bevy::render::mesh::Mesh {
+    fn from(buffer: MeshBuffer) -> Self {
+        use bevy::render::{mesh::VertexAttribute, pipeline::PrimitiveTopology};
+
+        Self {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(buffer.positions),
+                VertexAttribute::normal(buffer.normals),
+                VertexAttribute::uv(buffer.uvs),
+            ],
+            indices: Some(buffer.indices),
+        }
+    }
+}
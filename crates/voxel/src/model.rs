@@ -0,0 +1,136 @@
+use crate::math::Vec3;
+use crate::{Matrix, Voxel};
+use std::path::Path;
+
+/// A voxel model asset made up of one or more named parts, along with the metadata needed to place
+/// it in the world.
+#[derive(Debug)]
+pub struct VoxelModel {
+    pub name: String,
+    pub parts: Vec<VoxelModelPart>,
+    /// Point, measured in voxels, that the model is positioned and rotated around.
+    pub pivot: Vec3,
+    /// Size of a single voxel in world units.
+    pub voxel_size: f32,
+}
+
+impl VoxelModel {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            parts: Vec::new(),
+            pivot: Vec3::zero(),
+            voxel_size: 1.0,
+        }
+    }
+
+    /// Finds a part by name, searching nested parts depth first.
+    pub fn part(&self, name: &str) -> Option<&VoxelModelPart> {
+        fn find<'a>(parts: &'a [VoxelModelPart], name: &str) -> Option<&'a VoxelModelPart> {
+            parts.iter().find_map(|part| {
+                if part.name == name {
+                    Some(part)
+                } else {
+                    find(&part.children, name)
+                }
+            })
+        }
+
+        find(&self.parts, name)
+    }
+
+    /// Every matrix in the model, including those of nested parts, along with its name and
+    /// offset. Parts that only group other parts are skipped.
+    pub fn matrices(&self) -> Vec<(&str, &Matrix, Vec3)> {
+        fn collect<'a>(
+            parts: &'a [VoxelModelPart],
+            matrices: &mut Vec<(&'a str, &'a Matrix, Vec3)>,
+        ) {
+            for part in parts {
+                if !part.matrix.is_empty() {
+                    matrices.push((part.name.as_str(), &part.matrix, part.offset));
+                }
+
+                collect(&part.children, matrices);
+            }
+        }
+
+        let mut matrices = Vec::new();
+        collect(&self.parts, &mut matrices);
+        matrices
+    }
+
+    /// The corners of the box holding every matrix in the model, measured in voxels, or `None`
+    /// if the model has no matrices.
+    pub fn extents(&self) -> Option<(Vec3, Vec3)> {
+        let matrices = self.matrices();
+
+        if matrices.is_empty() {
+            return None;
+        }
+
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+
+        for (_, matrix, offset) in matrices.iter() {
+            let (size_x, size_y, size_z) = matrix.dimensions();
+            min = min.min(*offset);
+            max = max.max(*offset + Vec3::new(size_x as f32, size_y as f32, size_z as f32));
+        }
+
+        Some((min, max))
+    }
+
+    /// Combines every matrix in the model into one, for formats that only hold a single matrix.
+    /// Where parts overlap, later parts take precedence.
+    pub fn merged_matrix(&self) -> Matrix {
+        let (min, max) = match self.extents() {
+            Some(extents) => extents,
+            None => return Matrix::new(0, 0, 0),
+        };
+
+        let size = max - min;
+        let mut merged = Matrix::new(size.x() as usize, size.y() as usize, size.z() as usize);
+
+        for (_, matrix, offset) in self.matrices().iter() {
+            for (position, color) in matrix.solid_voxels() {
+                merged.set(position + *offset - min, Voxel::Solid(color));
+            }
+        }
+
+        merged
+    }
+}
+
+/// Most voxel formats do not store a name for the model as a whole so we use the file name.
+pub(crate) fn model_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// A single named matrix within a `VoxelModel`. Parts may be nested, in which case a part with an
+/// empty matrix is only used to group its children.
+#[derive(Debug)]
+pub struct VoxelModelPart {
+    pub name: String,
+    pub matrix: Matrix,
+    /// Position of the matrix within the model, measured in voxels.
+    pub offset: Vec3,
+    /// Point the part is rotated around when animated, measured in voxels from `offset`. The
+    /// entity spawned for the part sits at this point.
+    pub pivot: Vec3,
+    pub children: Vec<VoxelModelPart>,
+}
+
+impl VoxelModelPart {
+    pub fn new(name: String, matrix: Matrix, offset: Vec3) -> Self {
+        Self {
+            name,
+            matrix,
+            offset,
+            pivot: Vec3::zero(),
+            children: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use byteorder::{BigEndian, ByteOrder};
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+
+/// A value in Minecraft's Named Binary Tag format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    /// The value of any integer tag, widened to an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(i64::from(*value)),
+            Tag::Short(value) => Some(i64::from(*value)),
+            Tag::Int(value) => Some(i64::from(*value)),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// Minecraft allows 512 levels, but that overflows the stack of a test thread in debug builds.
+/// Schematics only nest block entities a few levels deep.
+const MAX_DEPTH: usize = 64;
+/// The largest file we will decompress, to guard against corrupt or malicious files.
+const MAX_DECOMPRESSED_LEN: u64 = 256 * 1024 * 1024;
+
+/// Reads the root tag of an NBT file along with its name. Gzip compressed files, which is how
+/// Minecraft stores them, are decompressed first.
+pub fn read(bytes: &[u8]) -> Result<(String, Tag), ReadError> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+
+        GzDecoder::new(bytes)
+            .take(MAX_DECOMPRESSED_LEN)
+            .read_to_end(&mut decompressed)
+            .map_err(|error| {
+                ReadError::new("gzip data", 0, ReadErrorKind::Invalid(error.to_string()))
+            })?;
+
+        return read_uncompressed(&decompressed);
+    }
+
+    read_uncompressed(bytes)
+}
+
+fn read_uncompressed(bytes: &[u8]) -> Result<(String, Tag), ReadError> {
+    let mut reader = ByteReader::new(bytes);
+
+    let offset = reader.offset();
+    let tag_type = reader.read_u8("root tag type")?;
+    if tag_type != COMPOUND {
+        return Err(ReadError::new(
+            "root tag type",
+            offset,
+            ReadErrorKind::Invalid(format!("expected a compound but found {}", tag_type)),
+        ));
+    }
+
+    let name = read_string(&mut reader)?;
+    let tag = read_payload(&mut reader, tag_type, 0)?;
+
+    Ok((name, tag))
+}
+
+fn read_payload(reader: &mut ByteReader, tag_type: u8, depth: usize) -> Result<Tag, ReadError> {
+    let offset = reader.offset();
+
+    if depth > MAX_DEPTH {
+        return Err(ReadError::new(
+            "tag",
+            offset,
+            ReadErrorKind::Invalid("tags are nested too deeply".to_string()),
+        ));
+    }
+
+    let tag = match tag_type {
+        BYTE => Tag::Byte(reader.read_u8("byte")? as i8),
+        SHORT => Tag::Short(BigEndian::read_i16(reader.read("short", 2)?)),
+        INT => Tag::Int(read_i32(reader, "int")?),
+        LONG => Tag::Long(BigEndian::read_i64(reader.read("long", 8)?)),
+        FLOAT => Tag::Float(BigEndian::read_f32(reader.read("float", 4)?)),
+        DOUBLE => Tag::Double(BigEndian::read_f64(reader.read("double", 8)?)),
+        BYTE_ARRAY => {
+            let len = read_len(reader, "byte array length")?;
+            Tag::ByteArray(reader.read("byte array", len)?.to_vec())
+        }
+        STRING => Tag::String(read_string(reader)?),
+        LIST => {
+            let item_type = reader.read_u8("list item type")?;
+            let len = read_len(reader, "list length")?;
+
+            let mut items = Vec::new();
+            for _ in 0..len {
+                items.push(read_payload(reader, item_type, depth + 1)?);
+            }
+
+            Tag::List(items)
+        }
+        COMPOUND => {
+            let mut tags = HashMap::new();
+
+            loop {
+                let item_type = reader.read_u8("tag type")?;
+                if item_type == END {
+                    break;
+                }
+
+                let name = read_string(reader)?;
+                tags.insert(name, read_payload(reader, item_type, depth + 1)?);
+            }
+
+            Tag::Compound(tags)
+        }
+        INT_ARRAY => {
+            let len = read_len(reader, "int array length")?;
+            let bytes = reader.read("int array", len.saturating_mul(4))?;
+            Tag::IntArray(bytes.chunks_exact(4).map(BigEndian::read_i32).collect())
+        }
+        LONG_ARRAY => {
+            let len = read_len(reader, "long array length")?;
+            let bytes = reader.read("long array", len.saturating_mul(8))?;
+            Tag::LongArray(bytes.chunks_exact(8).map(BigEndian::read_i64).collect())
+        }
+        _ => {
+            return Err(ReadError::new(
+                "tag type",
+                offset,
+                ReadErrorKind::Invalid(format!("unknown tag type {}", tag_type)),
+            ))
+        }
+    };
+
+    Ok(tag)
+}
+
+fn read_i32(reader: &mut ByteReader, field: &'static str) -> Result<i32, ReadError> {
+    Ok(BigEndian::read_i32(reader.read(field, 4)?))
+}
+
+/// Lengths are signed so we treat negative lengths as empty, as Minecraft does.
+fn read_len(reader: &mut ByteReader, field: &'static str) -> Result<usize, ReadError> {
+    Ok(usize::try_from(read_i32(reader, field)?).unwrap_or(0))
+}
+
+fn read_string(reader: &mut ByteReader) -> Result<String, ReadError> {
+    let len = BigEndian::read_u16(reader.read("string length", 2)?);
+
+    // Strings use Java's modified UTF-8, which only differs for characters we don't expect to
+    // see in block names.
+    let offset = reader.offset();
+    let bytes = reader.read("string", usize::from(len))?;
+
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| ReadError::new("string", offset, ReadErrorKind::InvalidUtf8))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// Writes an uncompressed NBT file, for building fixtures in tests.
+    pub(crate) fn write(name: &str, tag: &Tag) -> Vec<u8> {
+        let mut bytes = vec![tag_type(tag)];
+        write_string(&mut bytes, name);
+        write_payload(&mut bytes, tag);
+        bytes
+    }
+
+    pub(crate) fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Builds a compound from a list of named tags.
+    pub(crate) fn compound(tags: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(
+            tags.into_iter()
+                .map(|(name, tag)| (name.to_string(), tag))
+                .collect(),
+        )
+    }
+
+    fn tag_type(tag: &Tag) -> u8 {
+        match tag {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    fn write_string(bytes: &mut Vec<u8>, value: &str) {
+        bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_payload(bytes: &mut Vec<u8>, tag: &Tag) {
+        match tag {
+            Tag::Byte(value) => bytes.push(*value as u8),
+            Tag::Short(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => bytes.extend_from_slice(&value.to_bits().to_be_bytes()),
+            Tag::Double(value) => bytes.extend_from_slice(&value.to_bits().to_be_bytes()),
+            Tag::ByteArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                bytes.extend_from_slice(values);
+            }
+            Tag::String(value) => write_string(bytes, value),
+            Tag::List(items) => {
+                bytes.push(items.first().map_or(END, tag_type));
+                bytes.extend_from_slice(&(items.len() as i32).to_be_bytes());
+                for item in items.iter() {
+                    write_payload(bytes, item);
+                }
+            }
+            Tag::Compound(tags) => {
+                for (name, tag) in tags.iter() {
+                    bytes.push(tag_type(tag));
+                    write_string(bytes, name);
+                    write_payload(bytes, tag);
+                }
+                bytes.push(END);
+            }
+            Tag::IntArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                for value in values.iter() {
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Tag::LongArray(values) => {
+                bytes.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                for value in values.iter() {
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    fn fixture() -> Tag {
+        compound(vec![
+            ("byte", Tag::Byte(-3)),
+            ("short", Tag::Short(-300)),
+            ("int", Tag::Int(70_000)),
+            ("long", Tag::Long(-5_000_000_000)),
+            ("float", Tag::Float(1.5)),
+            ("double", Tag::Double(-0.25)),
+            ("bytes", Tag::ByteArray(vec![0, 127, 128, 255])),
+            ("string", Tag::String("minecraft:stone".to_string())),
+            (
+                "list",
+                Tag::List(vec![Tag::Short(1), Tag::Short(2), Tag::Short(3)]),
+            ),
+            ("empty list", Tag::List(Vec::new())),
+            ("compound", compound(vec![("nested", Tag::Int(7))])),
+            ("ints", Tag::IntArray(vec![-1, 0, 1])),
+            ("longs", Tag::LongArray(vec![i64::MIN, i64::MAX])),
+        ])
+    }
+
+    #[test]
+    fn reads_every_tag_type() {
+        let bytes = write("root", &fixture());
+
+        assert_eq!(read(&bytes).unwrap(), ("root".to_string(), fixture()));
+    }
+
+    #[test]
+    fn reads_gzip_compressed_files() {
+        let bytes = gzip(&write("root", &fixture()));
+
+        assert_eq!(read(&bytes).unwrap(), ("root".to_string(), fixture()));
+    }
+
+    #[test]
+    fn rejects_roots_that_are_not_compounds() {
+        assert!(read(&write("root", &Tag::Int(1))).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = write("root", &fixture());
+
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(&gzip(&bytes[..bytes.len() / 2])).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_gzip_data() {
+        let mut bytes = gzip(&write("root", &fixture()));
+        let len = bytes.len();
+        bytes.truncate(len - 10);
+
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_tag_types() {
+        let mut bytes = write("root", &compound(vec![("byte", Tag::Byte(1))]));
+        // The type of the first entry follows the root's type and name.
+        bytes[7] = 13;
+
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_deeply_nested_tags() {
+        let mut tag = Tag::List(Vec::new());
+        for _ in 0..=MAX_DEPTH {
+            tag = Tag::List(vec![tag]);
+        }
+
+        assert!(read(&write("root", &compound(vec![("list", tag)]))).is_err());
+    }
+}
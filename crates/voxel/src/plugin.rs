@@ -0,0 +1,23 @@
+use crate::magica_voxel::MagicaVoxelLoader;
+use crate::qb::QubicleBinaryLoader;
+use crate::qbt::QubicleBinaryTreeLoader;
+use crate::qef::QubicleExchangeLoader;
+use crate::schematic::SchematicLoader;
+use crate::{respawn_modified_voxel_model_system, spawn_voxel_model_system, VoxelModel};
+use bevy::prelude::{Plugin as BevyPlugin, *};
+
+#[derive(Default)]
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<VoxelModel>()
+            .add_asset_loader::<VoxelModel, QubicleBinaryLoader>()
+            .add_asset_loader::<VoxelModel, QubicleBinaryTreeLoader>()
+            .add_asset_loader::<VoxelModel, QubicleExchangeLoader>()
+            .add_asset_loader::<VoxelModel, MagicaVoxelLoader>()
+            .add_asset_loader::<VoxelModel, SchematicLoader>()
+            .add_system(spawn_voxel_model_system.system())
+            .add_system(respawn_modified_voxel_model_system.system());
+    }
+}
@@ -0,0 +1,748 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::{color_to_rgb_u8, model_name, Matrix, VisibleFaces, Voxel, VoxelModel, VoxelModelPart};
+#[cfg(feature = "bevy")]
+use bevy::asset::AssetLoader;
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[cfg(feature = "bevy")]
+#[derive(Default)]
+pub struct QubicleBinaryLoader;
+
+/// The order that colour channels are stored in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorFormat {
+    Rgba,
+    Bgra,
+}
+
+/// The direction of the Z axis. Left handed files are loaded as is and right handed files are
+/// mirrored along Z to match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ZAxisOrientation {
+    LeftHanded,
+    RightHanded,
+}
+
+/// The layout of the voxel data in a Qubicle Binary file.
+#[derive(Debug, Copy, Clone)]
+pub struct Format {
+    pub color_format: ColorFormat,
+    pub z_axis_orientation: ZAxisOrientation,
+    /// Whether each slice of a matrix is run length encoded.
+    pub compressed: bool,
+    /// Whether the alpha channel says which sides of a voxel are visible rather than just whether
+    /// it is solid.
+    pub visibility_mask_encoded: bool,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            color_format: ColorFormat::Rgba,
+            z_axis_orientation: ZAxisOrientation::LeftHanded,
+            compressed: false,
+            visibility_mask_encoded: false,
+        }
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl AssetLoader<VoxelModel> for QubicleBinaryLoader {
+    fn from_bytes(&self, path: &Path, bytes: Vec<u8>) -> anyhow::Result<VoxelModel, anyhow::Error> {
+        Ok(read(path, &bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["qb"];
+        EXTENSIONS
+    }
+}
+
+/// Reads a Qubicle Binary file into a model with a part for each matrix.
+pub fn read(path: &Path, bytes: &[u8]) -> Result<VoxelModel, ReadError> {
+    // Due to the way the .qb files are encoded we have to read the data even if we don't use it.
+    // Where data is read and not used the variable is prefixed with an _.
+    let mut reader = ByteReader::new(bytes);
+
+    let _version = reader.read_u32("version")?;
+
+    let format = Format {
+        color_format: if reader.read_u32("colour format")? == 0 {
+            ColorFormat::Rgba
+        } else {
+            ColorFormat::Bgra
+        },
+        z_axis_orientation: if reader.read_u32("z axis orientation")? == 0 {
+            ZAxisOrientation::LeftHanded
+        } else {
+            ZAxisOrientation::RightHanded
+        },
+        compressed: reader.read_u32("compressed")? != 0,
+        visibility_mask_encoded: reader.read_u32("visibility mask encoded")? != 0,
+    };
+
+    let num_matrices = reader.read_u32("matrix count")?;
+
+    let mut model = VoxelModel::new(model_name(path));
+    // Matrices are allocated before their voxels are read, and run length encoded data can
+    // describe a huge matrix in a few bytes, so the volume of the whole model is limited too.
+    let mut total_volume = 0;
+
+    for _ in 0..num_matrices {
+        let name_len = reader.read_u8("matrix name length")?;
+        let name = reader.read_string("matrix name", usize::from(name_len))?;
+
+        let size_offset = reader.offset();
+        let size_x = reader.read_len("matrix size x", Matrix::MAX_SIZE)?;
+        let size_y = reader.read_len("matrix size y", Matrix::MAX_SIZE)?;
+        let size_z = reader.read_len("matrix size z", Matrix::MAX_SIZE)?;
+
+        let volume = size_x * size_y * size_z;
+        if volume > Matrix::MAX_VOLUME {
+            return Err(ReadError::new(
+                "matrix size",
+                size_offset,
+                ReadErrorKind::TooLarge {
+                    value: volume as u64,
+                    max: Matrix::MAX_VOLUME,
+                },
+            ));
+        }
+
+        total_volume += volume;
+        if total_volume > Matrix::MAX_VOLUME {
+            return Err(ReadError::new(
+                "matrix size",
+                size_offset,
+                ReadErrorKind::TooLarge {
+                    value: total_volume as u64,
+                    max: Matrix::MAX_VOLUME,
+                },
+            ));
+        }
+
+        let mut matrix_position = Vec3::new(
+            reader.read_i32("matrix position x")? as f32,
+            reader.read_i32("matrix position y")? as f32,
+            reader.read_i32("matrix position z")? as f32,
+        );
+
+        if format.z_axis_orientation == ZAxisOrientation::RightHanded {
+            matrix_position.set_z(-matrix_position.z() - size_z as f32);
+        }
+
+        // Check that the file is long enough to hold the voxels before allocating the matrix.
+        // Uncompressed voxels take four bytes each and compressed slices at least four bytes.
+        let min_data_len = if format.compressed {
+            size_z * 4
+        } else {
+            volume * 4
+        };
+
+        if reader.remaining() < min_data_len {
+            return Err(ReadError::new(
+                "matrix data",
+                reader.offset(),
+                ReadErrorKind::UnexpectedEof {
+                    expected: min_data_len,
+                    remaining: reader.remaining(),
+                },
+            ));
+        }
+
+        let mut matrix = Matrix::new(size_x, size_y, size_z);
+
+        if format.compressed {
+            read_compressed_voxels(&mut reader, &format, &mut matrix)?;
+        } else {
+            read_uncompressed_voxels(&mut reader, &format, &mut matrix)?;
+        }
+
+        model
+            .parts
+            .push(VoxelModelPart::new(name, matrix, matrix_position));
+    }
+
+    Ok(model)
+}
+
+fn read_uncompressed_voxels(
+    reader: &mut ByteReader,
+    format: &Format,
+    matrix: &mut Matrix,
+) -> Result<(), ReadError> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    for z in 0..size_z {
+        for y in 0..size_y {
+            for x in 0..size_x {
+                format.set_voxel(matrix, x, y, z, reader.read_u32("voxel")?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks the end of a slice in run length encoded matrix data.
+const NEXT_SLICE_FLAG: u32 = 6;
+/// Marks a run of repeated voxels in run length encoded matrix data. It is followed by the run
+/// length and then the voxel data to repeat.
+const CODE_FLAG: u32 = 2;
+
+/// Compressed matrices are run length encoded one Z slice at a time, with the voxels of a slice
+/// stored row by row.
+fn read_compressed_voxels(
+    reader: &mut ByteReader,
+    format: &Format,
+    matrix: &mut Matrix,
+) -> Result<(), ReadError> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+    let slice_len = size_x * size_y;
+
+    for z in 0..size_z {
+        let mut index = 0;
+
+        loop {
+            let offset = reader.offset();
+            let data = reader.read_u32("voxel")?;
+
+            if data == NEXT_SLICE_FLAG {
+                break;
+            }
+
+            let (count, data) = if data == CODE_FLAG {
+                (
+                    reader.read_len("voxel run length", slice_len - index)?,
+                    reader.read_u32("voxel")?,
+                )
+            } else {
+                (1, data)
+            };
+
+            if index + count > slice_len {
+                return Err(ReadError::new(
+                    "voxel",
+                    offset,
+                    ReadErrorKind::Invalid(format!(
+                        "slice {} has more than {} voxels",
+                        z, slice_len
+                    )),
+                ));
+            }
+
+            for _ in 0..count {
+                format.set_voxel(matrix, index % size_x, index / size_x, z, data);
+                index += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Format {
+    /// Voxel data is stored as four colour bytes. If the last byte is 0 then the voxel is empty.
+    /// When the visibility mask is encoded the remaining bits of the last byte say which sides of
+    /// the voxel are visible.
+    fn set_voxel(&self, matrix: &mut Matrix, x: usize, y: usize, z: usize, data: u32) {
+        let (r, g, b, a) = match (self.color_format, data.to_le_bytes()) {
+            (ColorFormat::Rgba, [r, g, b, a]) => (r, g, b, a),
+            (ColorFormat::Bgra, [b, g, r, a]) => (r, g, b, a),
+        };
+
+        let z = match self.z_axis_orientation {
+            ZAxisOrientation::LeftHanded => z,
+            ZAxisOrientation::RightHanded => matrix.dimensions().2 - 1 - z,
+        };
+
+        let position = Vec3::new(x as f32, y as f32, z as f32);
+
+        if a == 0 {
+            matrix.set(position, Voxel::Empty);
+            return;
+        }
+
+        matrix.set(position, Voxel::Solid(Color::rgb_u8(r, g, b)));
+
+        if self.visibility_mask_encoded {
+            matrix.set_visible_faces(position, self.visible_faces(a));
+        }
+    }
+
+    /// The inverse of `set_voxel`, encoding the voxel at the given file position.
+    fn voxel_data(&self, matrix: &Matrix, x: usize, y: usize, z: usize) -> u32 {
+        let z = match self.z_axis_orientation {
+            ZAxisOrientation::LeftHanded => z,
+            ZAxisOrientation::RightHanded => matrix.dimensions().2 - 1 - z,
+        };
+
+        let position = Vec3::new(x as f32, y as f32, z as f32);
+
+        let color = match matrix.lookup(position) {
+            Voxel::Empty => return 0,
+            Voxel::Solid(color) => *color,
+        };
+
+        let [r, g, b] = color_to_rgb_u8(color);
+
+        let a = if self.visibility_mask_encoded {
+            self.visibility_mask(matrix.visible_faces(position))
+        } else {
+            u8::MAX
+        };
+
+        let bytes = match self.color_format {
+            ColorFormat::Rgba => [r, g, b, a],
+            ColorFormat::Bgra => [b, g, r, a],
+        };
+
+        u32::from_le_bytes(bytes)
+    }
+
+    pub(crate) fn visible_faces(&self, mask: u8) -> VisibleFaces {
+        let mut faces = VisibleFaces::NONE;
+
+        for (bit, side) in self.visibility_sides().iter() {
+            if mask & bit != 0 {
+                faces.insert(*side);
+            }
+        }
+
+        faces
+    }
+
+    /// The inverse of `visible_faces`, encoding the sides of a solid voxel that are visible.
+    pub(crate) fn visibility_mask(&self, faces: VisibleFaces) -> u8 {
+        let mut mask = VISIBILITY_SOLID;
+
+        for (bit, side) in self.visibility_sides().iter() {
+            if faces.contains(*side) {
+                mask |= bit;
+            }
+        }
+
+        mask
+    }
+
+    fn visibility_sides(&self) -> [(u8, VisibleFaces); 6] {
+        // Mirroring along Z swaps the front and back of each voxel.
+        let (front, back) = match self.z_axis_orientation {
+            ZAxisOrientation::LeftHanded => (VisibleFaces::FRONT, VisibleFaces::BACK),
+            ZAxisOrientation::RightHanded => (VisibleFaces::BACK, VisibleFaces::FRONT),
+        };
+
+        [
+            (VISIBILITY_LEFT, VisibleFaces::LEFT),
+            (VISIBILITY_RIGHT, VisibleFaces::RIGHT),
+            (VISIBILITY_TOP, VisibleFaces::TOP),
+            (VISIBILITY_BOTTOM, VisibleFaces::BOTTOM),
+            (VISIBILITY_FRONT, front),
+            (VISIBILITY_BACK, back),
+        ]
+    }
+}
+
+const VISIBILITY_SOLID: u8 = 1;
+const VISIBILITY_LEFT: u8 = 1 << 1;
+const VISIBILITY_RIGHT: u8 = 1 << 2;
+const VISIBILITY_TOP: u8 = 1 << 3;
+const VISIBILITY_BOTTOM: u8 = 1 << 4;
+const VISIBILITY_FRONT: u8 = 1 << 5;
+const VISIBILITY_BACK: u8 = 1 << 6;
+
+const VERSION: u32 = 0x0000_0101;
+
+/// Encodes a model as a Qubicle Binary file, writing one matrix per part.
+pub fn write<W: Write>(writer: &mut W, model: &VoxelModel, format: &Format) -> io::Result<()> {
+    write_matrices(writer, &model.matrices(), format)
+}
+
+/// Encodes a single matrix as a Qubicle Binary file.
+pub fn write_matrix<W: Write>(
+    writer: &mut W,
+    name: &str,
+    matrix: &Matrix,
+    format: &Format,
+) -> io::Result<()> {
+    write_matrices(writer, &[(name, matrix, Vec3::zero())], format)
+}
+
+fn write_matrices<W: Write>(
+    writer: &mut W,
+    matrices: &[(&str, &Matrix, Vec3)],
+    format: &Format,
+) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_u32::<LittleEndian>(match format.color_format {
+        ColorFormat::Rgba => 0,
+        ColorFormat::Bgra => 1,
+    })?;
+    writer.write_u32::<LittleEndian>(match format.z_axis_orientation {
+        ZAxisOrientation::LeftHanded => 0,
+        ZAxisOrientation::RightHanded => 1,
+    })?;
+    writer.write_u32::<LittleEndian>(format.compressed as u32)?;
+    writer.write_u32::<LittleEndian>(format.visibility_mask_encoded as u32)?;
+    writer.write_u32::<LittleEndian>(matrices.len() as u32)?;
+
+    for (name, matrix, offset) in matrices {
+        let (size_x, size_y, size_z) = matrix.dimensions();
+
+        // Names longer than the length byte allows are truncated on a character boundary.
+        let mut name_len = name.len().min(usize::from(u8::MAX));
+        while !name.is_char_boundary(name_len) {
+            name_len -= 1;
+        }
+
+        writer.write_u8(name_len as u8)?;
+        writer.write_all(&name.as_bytes()[..name_len])?;
+
+        writer.write_u32::<LittleEndian>(size_x as u32)?;
+        writer.write_u32::<LittleEndian>(size_y as u32)?;
+        writer.write_u32::<LittleEndian>(size_z as u32)?;
+
+        let offset_z = match format.z_axis_orientation {
+            ZAxisOrientation::LeftHanded => offset.z(),
+            ZAxisOrientation::RightHanded => -offset.z() - size_z as f32,
+        };
+
+        writer.write_i32::<LittleEndian>(offset.x() as i32)?;
+        writer.write_i32::<LittleEndian>(offset.y() as i32)?;
+        writer.write_i32::<LittleEndian>(offset_z as i32)?;
+
+        if format.compressed {
+            write_compressed_voxels(writer, format, matrix)?;
+        } else {
+            write_uncompressed_voxels(writer, format, matrix)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_uncompressed_voxels<W: Write>(
+    writer: &mut W,
+    format: &Format,
+    matrix: &Matrix,
+) -> io::Result<()> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    for z in 0..size_z {
+        for y in 0..size_y {
+            for x in 0..size_x {
+                writer.write_u32::<LittleEndian>(format.voxel_data(matrix, x, y, z))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_compressed_voxels<W: Write>(
+    writer: &mut W,
+    format: &Format,
+    matrix: &Matrix,
+) -> io::Result<()> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    for z in 0..size_z {
+        let slice: Vec<u32> = (0..size_x * size_y)
+            .map(|index| format.voxel_data(matrix, index % size_x, index / size_x, z))
+            .collect();
+
+        let mut index = 0;
+
+        while index < slice.len() {
+            let data = slice[index];
+            let run_len = slice[index..].iter().take_while(|&&d| d == data).count();
+
+            // A run only saves space once it is longer than the three values needed to encode it.
+            if run_len > 3 {
+                writer.write_u32::<LittleEndian>(CODE_FLAG)?;
+                writer.write_u32::<LittleEndian>(run_len as u32)?;
+                writer.write_u32::<LittleEndian>(data)?;
+            } else {
+                for _ in 0..run_len {
+                    writer.write_u32::<LittleEndian>(data)?;
+                }
+            }
+
+            index += run_len;
+        }
+
+        writer.write_u32::<LittleEndian>(NEXT_SLICE_FLAG)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    fn solid([r, g, b]: [u8; 3]) -> Voxel {
+        Voxel::Solid(Color::rgb_u8(r, g, b))
+    }
+
+    /// A model with two matrices of different sizes, holding long runs for run length encoding
+    /// along with single voxels. Faces are only hidden when the format can store them.
+    fn fixture(format: &Format) -> VoxelModel {
+        let mut model = VoxelModel::new("fixture".to_string());
+
+        let mut ground = Matrix::new(6, 2, 3);
+        for z in 0..3 {
+            for x in 0..6 {
+                ground.set(Vec3::new(x as f32, 0.0, z as f32), solid(GREEN));
+            }
+        }
+        ground.set(Vec3::new(2.0, 1.0, 1.0), solid(RED));
+        ground.set(Vec3::new(5.0, 1.0, 2.0), solid(BLUE));
+
+        let mut tower = Matrix::new(1, 4, 2);
+        for y in 0..4 {
+            tower.set(Vec3::new(0.0, y as f32, 0.0), solid(BLUE));
+        }
+
+        if format.visibility_mask_encoded {
+            let mut faces = VisibleFaces::TOP;
+            faces.insert(VisibleFaces::FRONT);
+            ground.set_visible_faces(Vec3::new(2.0, 1.0, 1.0), faces);
+            tower.set_visible_faces(Vec3::new(0.0, 3.0, 0.0), VisibleFaces::BACK);
+        }
+
+        model.parts.push(VoxelModelPart::new(
+            "ground".to_string(),
+            ground,
+            Vec3::new(-3.0, 0.0, 4.0),
+        ));
+        model.parts.push(VoxelModelPart::new(
+            "tower".to_string(),
+            tower,
+            Vec3::new(1.0, 2.0, -5.0),
+        ));
+
+        model
+    }
+
+    fn all_formats() -> Vec<Format> {
+        let mut formats = Vec::new();
+
+        for &color_format in [ColorFormat::Rgba, ColorFormat::Bgra].iter() {
+            for &z_axis_orientation in
+                [ZAxisOrientation::LeftHanded, ZAxisOrientation::RightHanded].iter()
+            {
+                for &compressed in [false, true].iter() {
+                    for &visibility_mask_encoded in [false, true].iter() {
+                        formats.push(Format {
+                            color_format,
+                            z_axis_orientation,
+                            compressed,
+                            visibility_mask_encoded,
+                        });
+                    }
+                }
+            }
+        }
+
+        formats
+    }
+
+    fn header(format: &Format, num_matrices: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_matrices(&mut bytes, &[], format).unwrap();
+        bytes.truncate(bytes.len() - 4);
+        bytes.write_u32::<LittleEndian>(num_matrices).unwrap();
+        bytes
+    }
+
+    fn write_matrix_header(bytes: &mut Vec<u8>, size: [u32; 3], position: [i32; 3]) {
+        bytes.write_u8(1).unwrap();
+        bytes.write_all(b"m").unwrap();
+        for value in size.iter() {
+            bytes.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        for value in position.iter() {
+            bytes.write_i32::<LittleEndian>(*value).unwrap();
+        }
+    }
+
+    fn rgba([r, g, b]: [u8; 3]) -> u32 {
+        u32::from_le_bytes([r, g, b, 255])
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for format in all_formats() {
+            let model = fixture(&format);
+
+            let mut bytes = Vec::new();
+            write(&mut bytes, &model, &format).unwrap();
+            let read_model = read(Path::new("fixture.qb"), &bytes).unwrap();
+
+            assert_eq!(read_model.name, "fixture");
+            assert_eq!(read_model.parts.len(), model.parts.len(), "{:?}", format);
+
+            for (read_part, part) in read_model.parts.iter().zip(model.parts.iter()) {
+                assert_eq!(read_part.name, part.name, "{:?}", format);
+                assert_eq!(read_part.offset, part.offset, "{:?}", format);
+                assert_eq!(read_part.matrix, part.matrix, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_data_is_smaller_for_runs() {
+        let uncompressed = Format::default();
+        let compressed = Format {
+            compressed: true,
+            ..Format::default()
+        };
+
+        let mut uncompressed_bytes = Vec::new();
+        write(
+            &mut uncompressed_bytes,
+            &fixture(&uncompressed),
+            &uncompressed,
+        )
+        .unwrap();
+        let mut compressed_bytes = Vec::new();
+        write(&mut compressed_bytes, &fixture(&compressed), &compressed).unwrap();
+
+        assert!(compressed_bytes.len() < uncompressed_bytes.len());
+    }
+
+    #[test]
+    fn decodes_runs_and_slice_ends() {
+        let format = Format {
+            compressed: true,
+            ..Format::default()
+        };
+
+        let mut bytes = header(&format, 1);
+        write_matrix_header(&mut bytes, [3, 2, 2], [0, 0, 0]);
+
+        // The first slice is a run of four red voxels followed by a single green one, leaving the
+        // last voxel empty when the slice ends early.
+        for value in [CODE_FLAG, 4, rgba(RED), rgba(GREEN), NEXT_SLICE_FLAG].iter() {
+            bytes.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        // The second slice is empty.
+        bytes.write_u32::<LittleEndian>(NEXT_SLICE_FLAG).unwrap();
+
+        let model = read(Path::new("runs.qb"), &bytes).unwrap();
+        let matrix = &model.parts[0].matrix;
+
+        for (x, y) in [(0, 0), (1, 0), (2, 0), (0, 1)].iter() {
+            assert_eq!(
+                *matrix.lookup(Vec3::new(*x as f32, *y as f32, 0.0)),
+                solid(RED)
+            );
+        }
+        assert_eq!(*matrix.lookup(Vec3::new(1.0, 1.0, 0.0)), solid(GREEN));
+        assert_eq!(*matrix.lookup(Vec3::new(2.0, 1.0, 0.0)), Voxel::Empty);
+        assert_eq!(matrix.solid_voxels().count(), 5);
+    }
+
+    #[test]
+    fn rejects_runs_longer_than_a_slice() {
+        let format = Format {
+            compressed: true,
+            ..Format::default()
+        };
+
+        let mut bytes = header(&format, 1);
+        write_matrix_header(&mut bytes, [2, 2, 1], [0, 0, 0]);
+
+        for value in [rgba(RED), CODE_FLAG, 4, rgba(GREEN), NEXT_SLICE_FLAG].iter() {
+            bytes.write_u32::<LittleEndian>(*value).unwrap();
+        }
+
+        assert!(read(Path::new("runs.qb"), &bytes).is_err());
+    }
+
+    #[test]
+    fn mirrors_right_handed_files_along_z() {
+        let format = Format {
+            z_axis_orientation: ZAxisOrientation::RightHanded,
+            visibility_mask_encoded: true,
+            ..Format::default()
+        };
+
+        let mut bytes = header(&format, 1);
+        write_matrix_header(&mut bytes, [1, 1, 3], [0, 0, 2]);
+
+        // Only the first voxel in the file is solid, with just its front side visible.
+        let mask = VISIBILITY_SOLID | VISIBILITY_FRONT;
+        for value in [u32::from_le_bytes([255, 0, 0, mask]), 0, 0].iter() {
+            bytes.write_u32::<LittleEndian>(*value).unwrap();
+        }
+
+        let model = read(Path::new("right.qb"), &bytes).unwrap();
+        let part = &model.parts[0];
+
+        assert_eq!(part.offset, Vec3::new(0.0, 0.0, -5.0));
+        assert_eq!(*part.matrix.lookup(Vec3::new(0.0, 0.0, 2.0)), solid(RED));
+        assert_eq!(
+            part.matrix.visible_faces(Vec3::new(0.0, 0.0, 2.0)),
+            VisibleFaces::BACK
+        );
+    }
+
+    #[test]
+    fn reads_bgra_channels() {
+        let format = Format {
+            color_format: ColorFormat::Bgra,
+            ..Format::default()
+        };
+
+        let mut bytes = header(&format, 1);
+        write_matrix_header(&mut bytes, [1, 1, 1], [0, 0, 0]);
+        bytes
+            .write_u32::<LittleEndian>(u32::from_le_bytes([10, 20, 30, 255]))
+            .unwrap();
+
+        let model = read(Path::new("bgra.qb"), &bytes).unwrap();
+
+        assert_eq!(
+            *model.parts[0].matrix.lookup(Vec3::zero()),
+            Voxel::Solid(Color::rgb_u8(30, 20, 10))
+        );
+    }
+
+    #[test]
+    fn rejects_models_larger_than_the_volume_limit() {
+        let format = Format {
+            compressed: true,
+            ..Format::default()
+        };
+
+        // Each matrix is within the limit and compressed slices take four bytes each, but the
+        // model as a whole is twice the limit.
+        let mut bytes = header(&format, 2);
+        for _ in 0..2 {
+            write_matrix_header(&mut bytes, [256, 256, 256], [0, 0, 0]);
+            for _ in 0..256 {
+                bytes.write_u32::<LittleEndian>(NEXT_SLICE_FLAG).unwrap();
+            }
+        }
+
+        assert!(read(Path::new("large.qb"), &bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_uncompressed_data_shorter_than_the_matrix() {
+        let mut bytes = header(&Format::default(), 1);
+        write_matrix_header(&mut bytes, [64, 64, 64], [0, 0, 0]);
+        bytes.write_u32::<LittleEndian>(rgba(RED)).unwrap();
+
+        assert!(read(Path::new("short.qb"), &bytes).is_err());
+    }
+}
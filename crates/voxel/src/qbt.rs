@@ -0,0 +1,539 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::qb::Format;
+use crate::{model_name, Matrix, Voxel, VoxelModel, VoxelModelPart};
+#[cfg(feature = "bevy")]
+use bevy::asset::AssetLoader;
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+use std::path::Path;
+
+/// Loads Qubicle Binary Tree files. Model and compound nodes keep their children so the hierarchy
+/// is spawned as nested entities.
+#[cfg(feature = "bevy")]
+#[derive(Default)]
+pub struct QubicleBinaryTreeLoader;
+
+#[cfg(feature = "bevy")]
+impl AssetLoader<VoxelModel> for QubicleBinaryTreeLoader {
+    fn from_bytes(&self, path: &Path, bytes: Vec<u8>) -> anyhow::Result<VoxelModel, anyhow::Error> {
+        Ok(read(path, &bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["qbt"];
+        EXTENSIONS
+    }
+}
+
+const MATRIX_NODE: u32 = 0;
+const MODEL_NODE: u32 = 1;
+const COMPOUND_NODE: u32 = 2;
+
+const MAX_NODE_DEPTH: usize = 64;
+const MAX_NODE_CHILDREN: usize = 64 * 1024;
+const MAX_NAME_LEN: usize = 64 * 1024;
+
+/// Reads a Qubicle Binary Tree file into a model, keeping its hierarchy of parts.
+pub fn read(path: &Path, bytes: &[u8]) -> Result<VoxelModel, ReadError> {
+    let mut reader = ByteReader::new(bytes);
+
+    expect(&mut reader, "magic number", b"QB 2")?;
+
+    let _version_major = reader.read_u8("major version")?;
+    let _version_minor = reader.read_u8("minor version")?;
+    let scale_offset = reader.offset();
+    let global_scale = Vec3::new(
+        reader.read_f32("global scale x")?,
+        reader.read_f32("global scale y")?,
+        reader.read_f32("global scale z")?,
+    );
+
+    if ![global_scale.x(), global_scale.y(), global_scale.z()]
+        .iter()
+        .all(|scale| scale.is_finite() && *scale > 0.0)
+    {
+        return Err(ReadError::new(
+            "global scale",
+            scale_offset,
+            ReadErrorKind::Invalid("scale must be positive".to_string()),
+        ));
+    }
+
+    expect(&mut reader, "colour map section", b"COLORMAP")?;
+
+    let color_count = reader.read_len("colour count", 256)?;
+    let mut colors = Vec::with_capacity(color_count);
+    for _ in 0..color_count {
+        let rgba = reader.read("colour", 4)?;
+        colors.push(Color::rgb_u8(rgba[0], rgba[1], rgba[2]));
+    }
+
+    expect(&mut reader, "data tree section", b"DATATREE")?;
+
+    let tree = Tree { colors };
+    let (node_type, root) = tree.read_node(&mut reader, 0)?;
+
+    let mut model = VoxelModel::new(model_name(path));
+    // Models are scaled uniformly, so a scale that differs between axes uses the X axis.
+    model.voxel_size = global_scale.x();
+
+    // A model node at the root only groups the rest of the tree so we use its children as parts.
+    // Otherwise the root matrix's pivot is the pivot of the whole model.
+    if node_type == MODEL_NODE {
+        model.parts = root.children;
+    } else {
+        model.pivot = root.offset + root.pivot;
+        model.parts.push(root);
+    }
+
+    Ok(model)
+}
+
+fn expect(reader: &mut ByteReader, field: &'static str, expected: &[u8]) -> Result<(), ReadError> {
+    let offset = reader.offset();
+
+    if reader.read(field, expected.len())? != expected {
+        return Err(ReadError::new(
+            field,
+            offset,
+            ReadErrorKind::Invalid(format!("expected {:?}", String::from_utf8_lossy(expected))),
+        ));
+    }
+
+    Ok(())
+}
+
+struct Tree {
+    /// When the file has a colour map, voxels store an index into it rather than a colour.
+    colors: Vec<Color>,
+}
+
+impl Tree {
+    fn read_node(
+        &self,
+        reader: &mut ByteReader,
+        depth: usize,
+    ) -> Result<(u32, VoxelModelPart), ReadError> {
+        let offset = reader.offset();
+
+        if depth > MAX_NODE_DEPTH {
+            return Err(ReadError::new(
+                "node",
+                offset,
+                ReadErrorKind::Invalid("data tree is too deep".to_string()),
+            ));
+        }
+
+        let node_type = reader.read_u32("node type")?;
+        let data_len = reader.read_len("node data size", usize::MAX)?;
+        let mut data = reader.sub_reader("node data", data_len)?;
+
+        let part = match node_type {
+            MATRIX_NODE => self.read_matrix(&mut data)?,
+            MODEL_NODE => {
+                let mut part =
+                    VoxelModelPart::new("model".to_string(), Matrix::new(0, 0, 0), Vec3::zero());
+                part.children = self.read_children(&mut data, depth)?;
+                part
+            }
+            COMPOUND_NODE => {
+                let mut part = self.read_matrix(&mut data)?;
+                part.children = self.read_children(&mut data, depth)?;
+                part
+            }
+            _ => {
+                return Err(ReadError::new(
+                    "node type",
+                    offset,
+                    ReadErrorKind::Invalid(format!("unknown node type {}", node_type)),
+                ))
+            }
+        };
+
+        Ok((node_type, part))
+    }
+
+    fn read_children(
+        &self,
+        reader: &mut ByteReader,
+        depth: usize,
+    ) -> Result<Vec<VoxelModelPart>, ReadError> {
+        let count = reader.read_len("child count", MAX_NODE_CHILDREN)?;
+        let mut children = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let (_, child) = self.read_node(reader, depth + 1)?;
+            children.push(child);
+        }
+
+        Ok(children)
+    }
+
+    fn read_matrix(&self, reader: &mut ByteReader) -> Result<VoxelModelPart, ReadError> {
+        let name_len = reader.read_len("matrix name length", MAX_NAME_LEN)?;
+        let name = reader.read_string("matrix name", name_len)?;
+
+        let position = Vec3::new(
+            reader.read_i32("matrix position x")? as f32,
+            reader.read_i32("matrix position y")? as f32,
+            reader.read_i32("matrix position z")? as f32,
+        );
+
+        let _local_scale = (
+            reader.read_u32("matrix local scale x")?,
+            reader.read_u32("matrix local scale y")?,
+            reader.read_u32("matrix local scale z")?,
+        );
+
+        let pivot_offset = reader.offset();
+        let pivot = Vec3::new(
+            reader.read_f32("matrix pivot x")?,
+            reader.read_f32("matrix pivot y")?,
+            reader.read_f32("matrix pivot z")?,
+        );
+
+        if ![pivot.x(), pivot.y(), pivot.z()]
+            .iter()
+            .all(|value| value.is_finite())
+        {
+            return Err(ReadError::new(
+                "matrix pivot",
+                pivot_offset,
+                ReadErrorKind::Invalid("pivot is not finite".to_string()),
+            ));
+        }
+
+        let size_offset = reader.offset();
+        let size_x = reader.read_len("matrix size x", Matrix::MAX_SIZE)?;
+        let size_y = reader.read_len("matrix size y", Matrix::MAX_SIZE)?;
+        let size_z = reader.read_len("matrix size z", Matrix::MAX_SIZE)?;
+
+        let volume = size_x * size_y * size_z;
+        if volume > Matrix::MAX_VOLUME {
+            return Err(ReadError::new(
+                "matrix size",
+                size_offset,
+                ReadErrorKind::TooLarge {
+                    value: volume as u64,
+                    max: Matrix::MAX_VOLUME,
+                },
+            ));
+        }
+
+        let data_len = reader.read_len("matrix data size", usize::MAX)?;
+        let data_offset = reader.offset();
+        let compressed = reader.read("matrix data", data_len)?;
+
+        // Only read as much as the matrix needs so a corrupt stream cannot make us allocate more.
+        let mut voxels = Vec::with_capacity(volume * 4);
+        ZlibDecoder::new(compressed)
+            .take(volume as u64 * 4)
+            .read_to_end(&mut voxels)
+            .map_err(|error| {
+                ReadError::new(
+                    "matrix data",
+                    data_offset,
+                    ReadErrorKind::Invalid(error.to_string()),
+                )
+            })?;
+
+        if voxels.len() < volume * 4 {
+            return Err(ReadError::new(
+                "matrix data",
+                data_offset,
+                ReadErrorKind::Invalid(format!(
+                    "expected {} bytes of voxels but found {}",
+                    volume * 4,
+                    voxels.len()
+                )),
+            ));
+        }
+
+        let format = Format::default();
+        let mut matrix = Matrix::new(size_x, size_y, size_z);
+        let mut voxels = voxels.chunks_exact(4);
+
+        // Voxels are stored in columns along Y, rather than rows along X like Qubicle Binary.
+        for x in 0..size_x {
+            for z in 0..size_z {
+                for y in 0..size_y {
+                    let voxel = voxels.next().unwrap();
+                    let mask = voxel[3];
+
+                    if mask == 0 {
+                        continue;
+                    }
+
+                    let color = if self.colors.is_empty() {
+                        Color::rgb_u8(voxel[0], voxel[1], voxel[2])
+                    } else {
+                        *self.colors.get(usize::from(voxel[0])).ok_or_else(|| {
+                            ReadError::new(
+                                "matrix data",
+                                data_offset,
+                                ReadErrorKind::Invalid(format!(
+                                    "colour index {} is out of range",
+                                    voxel[0]
+                                )),
+                            )
+                        })?
+                    };
+
+                    let position = Vec3::new(x as f32, y as f32, z as f32);
+                    matrix.set(position, Voxel::Solid(color));
+                    matrix.set_visible_faces(position, format.visible_faces(mask));
+                }
+            }
+        }
+
+        let mut part = VoxelModelPart::new(name, matrix, position);
+        part.pivot = pivot;
+        Ok(part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VisibleFaces;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    /// A solid voxel with every side visible.
+    const ALL_SIDES: u8 = 0x7f;
+
+    struct Fixture {
+        name: &'static str,
+        position: [i32; 3],
+        pivot: [f32; 3],
+        size: [u32; 3],
+        /// Voxels in file order, X then Z then Y, with the visibility mask in the last byte.
+        voxels: Vec<[u8; 4]>,
+    }
+
+    fn header(global_scale: f32, colors: &[[u8; 4]]) -> Vec<u8> {
+        let mut bytes = b"QB 2".to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        for _ in 0..3 {
+            bytes.write_f32::<LittleEndian>(global_scale).unwrap();
+        }
+
+        bytes.extend_from_slice(b"COLORMAP");
+        bytes
+            .write_u32::<LittleEndian>(colors.len() as u32)
+            .unwrap();
+        for color in colors.iter() {
+            bytes.extend_from_slice(color);
+        }
+
+        bytes.extend_from_slice(b"DATATREE");
+        bytes
+    }
+
+    fn matrix_data(fixture: &Fixture) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(fixture.name.len() as u32)
+            .unwrap();
+        data.extend_from_slice(fixture.name.as_bytes());
+        for value in fixture.position.iter() {
+            data.write_i32::<LittleEndian>(*value).unwrap();
+        }
+        for _ in 0..3 {
+            data.write_u32::<LittleEndian>(1).unwrap();
+        }
+        for value in fixture.pivot.iter() {
+            data.write_f32::<LittleEndian>(*value).unwrap();
+        }
+        for value in fixture.size.iter() {
+            data.write_u32::<LittleEndian>(*value).unwrap();
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for voxel in fixture.voxels.iter() {
+            encoder.write_all(voxel).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+
+        data.write_u32::<LittleEndian>(compressed.len() as u32)
+            .unwrap();
+        data.extend_from_slice(&compressed);
+        data
+    }
+
+    fn node(node_type: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.write_u32::<LittleEndian>(node_type).unwrap();
+        bytes.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn with_children(mut data: Vec<u8>, children: &[Vec<u8>]) -> Vec<u8> {
+        data.write_u32::<LittleEndian>(children.len() as u32)
+            .unwrap();
+        for child in children.iter() {
+            data.extend_from_slice(child);
+        }
+        data
+    }
+
+    /// A 2x3x2 matrix coloured by Y with alternate voxels empty, so reading the voxels in any
+    /// order but X, Z, Y moves them.
+    fn body() -> Fixture {
+        let mut voxels = Vec::new();
+        for x in 0..2 {
+            for z in 0..2 {
+                for y in 0..3 {
+                    let mask = if (x + y + z) % 2 == 0 { ALL_SIDES } else { 0 };
+                    voxels.push([(y % 2) as u8, 0, 0, mask]);
+                }
+            }
+        }
+
+        Fixture {
+            name: "body",
+            position: [1, 2, -3],
+            pivot: [1.0, 1.5, 1.0],
+            size: [2, 3, 2],
+            voxels,
+        }
+    }
+
+    fn arm() -> Fixture {
+        Fixture {
+            name: "arm",
+            position: [3, 3, -3],
+            pivot: [0.5, 0.5, 0.0],
+            size: [1, 1, 2],
+            // The first voxel is solid with only its top visible.
+            voxels: vec![[1, 0, 0, 1 | 1 << 3], [0, 0, 0, 0]],
+        }
+    }
+
+    fn solid([r, g, b, _]: [u8; 4]) -> Voxel {
+        Voxel::Solid(Color::rgb_u8(r, g, b))
+    }
+
+    #[test]
+    fn reads_compound_nodes_with_a_colour_map() {
+        let mut bytes = header(0.5, &[RED, BLUE]);
+        let arm = node(MATRIX_NODE, &matrix_data(&arm()));
+        bytes.extend(node(
+            COMPOUND_NODE,
+            &with_children(matrix_data(&body()), &[arm]),
+        ));
+
+        let model = read(Path::new("robot.qbt"), &bytes).unwrap();
+
+        assert_eq!(model.name, "robot");
+        assert_eq!(model.voxel_size, 0.5);
+        assert_eq!(model.pivot, Vec3::new(2.0, 3.5, -2.0));
+        assert_eq!(model.parts.len(), 1);
+
+        let body = &model.parts[0];
+        assert_eq!(body.name, "body");
+        assert_eq!(body.offset, Vec3::new(1.0, 2.0, -3.0));
+        assert_eq!(body.pivot, Vec3::new(1.0, 1.5, 1.0));
+        assert_eq!(body.matrix.dimensions(), (2, 3, 2));
+
+        for x in 0..2 {
+            for y in 0..3 {
+                for z in 0..2 {
+                    let position = Vec3::new(x as f32, y as f32, z as f32);
+                    let expected = if (x + y + z) % 2 != 0 {
+                        Voxel::Empty
+                    } else if y % 2 == 0 {
+                        solid(RED)
+                    } else {
+                        solid(BLUE)
+                    };
+
+                    assert_eq!(*body.matrix.lookup(position), expected, "{:?}", position);
+                }
+            }
+        }
+        assert_eq!(body.matrix.visible_faces(Vec3::zero()), VisibleFaces::ALL);
+
+        assert_eq!(body.children.len(), 1);
+        let arm = &body.children[0];
+        assert_eq!(arm.name, "arm");
+        assert_eq!(arm.offset, Vec3::new(3.0, 3.0, -3.0));
+        assert_eq!(arm.pivot, Vec3::new(0.5, 0.5, 0.0));
+        assert_eq!(*arm.matrix.lookup(Vec3::zero()), solid(BLUE));
+        assert_eq!(arm.matrix.visible_faces(Vec3::zero()), VisibleFaces::TOP);
+        assert_eq!(*arm.matrix.lookup(Vec3::new(0.0, 0.0, 1.0)), Voxel::Empty);
+    }
+
+    #[test]
+    fn uses_the_children_of_a_root_model_node_as_parts() {
+        let mut bytes = header(1.0, &[RED, BLUE]);
+        let children = [
+            node(MATRIX_NODE, &matrix_data(&body())),
+            node(MATRIX_NODE, &matrix_data(&arm())),
+        ];
+        bytes.extend(node(MODEL_NODE, &with_children(Vec::new(), &children)));
+
+        let model = read(Path::new("robot.qbt"), &bytes).unwrap();
+
+        let names: Vec<_> = model.parts.iter().map(|part| part.name.as_str()).collect();
+        assert_eq!(names, vec!["body", "arm"]);
+        assert_eq!(model.pivot, Vec3::zero());
+    }
+
+    #[test]
+    fn reads_colours_directly_without_a_colour_map() {
+        let mut bytes = header(1.0, &[]);
+        let fixture = Fixture {
+            size: [1, 1, 1],
+            voxels: vec![[10, 20, 30, ALL_SIDES]],
+            ..arm()
+        };
+        bytes.extend(node(MATRIX_NODE, &matrix_data(&fixture)));
+
+        let model = read(Path::new("plain.qbt"), &bytes).unwrap();
+
+        assert_eq!(
+            *model.parts[0].matrix.lookup(Vec3::zero()),
+            solid([10, 20, 30, 255])
+        );
+    }
+
+    #[test]
+    fn rejects_short_voxel_data() {
+        let mut bytes = header(1.0, &[RED, BLUE]);
+        let mut fixture = body();
+        fixture.voxels.pop();
+        bytes.extend(node(MATRIX_NODE, &matrix_data(&fixture)));
+
+        let error = read(Path::new("short.qbt"), &bytes).unwrap_err();
+
+        assert_eq!(error.field, "matrix data");
+    }
+
+    #[test]
+    fn rejects_colour_indices_outside_the_colour_map() {
+        let mut bytes = header(1.0, &[RED]);
+        bytes.extend(node(MATRIX_NODE, &matrix_data(&body())));
+
+        let error = read(Path::new("colours.qbt"), &bytes).unwrap_err();
+
+        assert_eq!(error.field, "matrix data");
+    }
+
+    #[test]
+    fn rejects_scales_that_are_not_positive() {
+        for scale in [0.0, -1.0, f32::NAN].iter() {
+            let mut bytes = header(*scale, &[RED, BLUE]);
+            bytes.extend(node(MATRIX_NODE, &matrix_data(&body())));
+
+            let error = read(Path::new("scale.qbt"), &bytes).unwrap_err();
+
+            assert_eq!(error.field, "global scale");
+        }
+    }
+}
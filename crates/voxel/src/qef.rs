@@ -0,0 +1,370 @@
+use crate::bytes::{ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::qb::Format;
+use crate::{color_to_rgb_u8, model_name, Matrix, Voxel, VoxelModel, VoxelModelPart};
+#[cfg(feature = "bevy")]
+use bevy::asset::AssetLoader;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::{FromStr, Lines};
+
+/// Loads the ASCII Qubicle Exchange Format. A file holds a single matrix which becomes the only
+/// part of the model.
+#[cfg(feature = "bevy")]
+#[derive(Default)]
+pub struct QubicleExchangeLoader;
+
+#[cfg(feature = "bevy")]
+impl AssetLoader<VoxelModel> for QubicleExchangeLoader {
+    fn from_bytes(&self, path: &Path, bytes: Vec<u8>) -> anyhow::Result<VoxelModel, anyhow::Error> {
+        Ok(read(path, &bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["qef"];
+        EXTENSIONS
+    }
+}
+
+/// Reads a Qubicle Exchange Format file into a model with a single part. Errors give the line
+/// they were found on rather than a byte offset.
+pub fn read(path: &Path, bytes: &[u8]) -> Result<VoxelModel, ReadError> {
+    let text = std::str::from_utf8(bytes)
+        .map_err(|error| ReadError::new("text", error.valid_up_to(), ReadErrorKind::InvalidUtf8))?;
+    let mut lines = LineReader::new(text);
+
+    let magic = lines.next_line("header")?;
+    if magic.trim() != MAGIC {
+        return Err(lines.error("header", "not a Qubicle Exchange Format file"));
+    }
+
+    let _version = lines.next_line("version")?;
+    let _website = lines.next_line("website")?;
+
+    let size = lines.next_values::<usize>("matrix size", 3)?;
+    let (size_x, size_y, size_z) = (size[0], size[1], size[2]);
+
+    if let Some(size) = [size_x, size_y, size_z]
+        .iter()
+        .find(|size| **size > Matrix::MAX_SIZE)
+    {
+        return Err(lines.too_large("matrix size", *size, Matrix::MAX_SIZE));
+    }
+
+    let volume = size_x * size_y * size_z;
+    if volume > Matrix::MAX_VOLUME {
+        return Err(lines.too_large("matrix size", volume, Matrix::MAX_VOLUME));
+    }
+
+    let color_count = lines.next_values::<usize>("colour count", 1)?[0];
+    let mut colors = Vec::new();
+
+    for _ in 0..color_count {
+        let rgb = lines.next_values::<f32>("colour", 3)?;
+        colors.push(Color::rgb(rgb[0], rgb[1], rgb[2]));
+    }
+
+    let format = Format::default();
+    let mut matrix = Matrix::new(size_x, size_y, size_z);
+
+    while let Some(line) = lines.next_non_empty() {
+        let values = lines.parse_values::<usize>(line, "voxel", 5)?;
+        let (x, y, z, color_index, mask) = (values[0], values[1], values[2], values[3], values[4]);
+
+        if x >= size_x || y >= size_y || z >= size_z {
+            return Err(lines.error("voxel", "position is outside of the matrix"));
+        }
+
+        let color = *colors
+            .get(color_index)
+            .ok_or_else(|| lines.error("voxel", "colour index is out of range"))?;
+
+        // As with Qubicle Binary files a mask of 0 means the voxel is not visible and the
+        // remaining bits say which sides are.
+        if mask == 0 {
+            continue;
+        }
+
+        let position = Vec3::new(x as f32, y as f32, z as f32);
+        matrix.set(position, Voxel::Solid(color));
+        matrix.set_visible_faces(position, format.visible_faces(mask as u8));
+    }
+
+    let name = model_name(path);
+    let mut model = VoxelModel::new(name.clone());
+    model
+        .parts
+        .push(VoxelModelPart::new(name, matrix, Vec3::zero()));
+
+    Ok(model)
+}
+
+const MAGIC: &str = "Qubicle Exchange Format";
+const VERSION: &str = "Version 0.2";
+const WEBSITE: &str = "www.minddesk.com";
+
+/// Encodes a matrix in the Qubicle Exchange Format, including the visible sides of each voxel.
+pub fn write<W: Write>(writer: &mut W, matrix: &Matrix) -> io::Result<()> {
+    let (size_x, size_y, size_z) = matrix.dimensions();
+
+    let mut colors = Vec::new();
+    let mut indices = HashMap::new();
+    let mut voxels = Vec::new();
+
+    for (position, color) in matrix.solid_voxels() {
+        let rgb = color_to_rgb_u8(color);
+        let index = *indices.entry(rgb).or_insert_with(|| {
+            colors.push(rgb);
+            colors.len() - 1
+        });
+
+        voxels.push((position, index));
+    }
+
+    writeln!(writer, "{}", MAGIC)?;
+    writeln!(writer, "{}", VERSION)?;
+    writeln!(writer, "{}", WEBSITE)?;
+    writeln!(writer, "{} {} {}", size_x, size_y, size_z)?;
+    writeln!(writer, "{}", colors.len())?;
+
+    for [r, g, b] in colors.iter() {
+        writeln!(
+            writer,
+            "{} {} {}",
+            f32::from(*r) / 255.0,
+            f32::from(*g) / 255.0,
+            f32::from(*b) / 255.0
+        )?;
+    }
+
+    let format = Format::default();
+
+    for (position, index) in voxels {
+        writeln!(
+            writer,
+            "{} {} {} {} {}",
+            position.x() as usize,
+            position.y() as usize,
+            position.z() as usize,
+            index,
+            format.visibility_mask(matrix.visible_faces(position))
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Reads lines of whitespace separated values, keeping track of the line number for errors.
+struct LineReader<'a> {
+    lines: Lines<'a>,
+    line_number: usize,
+}
+
+impl<'a> LineReader<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines(),
+            line_number: 0,
+        }
+    }
+
+    fn next_line(&mut self, field: &'static str) -> Result<&'a str, ReadError> {
+        self.line_number += 1;
+
+        self.lines
+            .next()
+            .ok_or_else(|| self.error(field, "unexpected end of file"))
+    }
+
+    fn next_non_empty(&mut self) -> Option<&'a str> {
+        loop {
+            self.line_number += 1;
+            let line = self.lines.next()?;
+
+            if !line.trim().is_empty() {
+                return Some(line);
+            }
+        }
+    }
+
+    fn next_values<T: FromStr>(
+        &mut self,
+        field: &'static str,
+        count: usize,
+    ) -> Result<Vec<T>, ReadError> {
+        let line = self.next_line(field)?;
+        self.parse_values(line, field, count)
+    }
+
+    fn parse_values<T: FromStr>(
+        &self,
+        line: &str,
+        field: &'static str,
+        count: usize,
+    ) -> Result<Vec<T>, ReadError> {
+        let values: Vec<T> = line
+            .split_whitespace()
+            .take(count)
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()
+            .ok_or_else(|| self.error(field, "expected a number"))?;
+
+        if values.len() < count {
+            return Err(self.error(field, &format!("expected {} numbers", count)));
+        }
+
+        Ok(values)
+    }
+
+    fn error(&self, field: &'static str, reason: &str) -> ReadError {
+        ReadError::on_line(
+            field,
+            self.line_number,
+            ReadErrorKind::Invalid(reason.to_string()),
+        )
+    }
+
+    fn too_large(&self, field: &'static str, value: usize, max: usize) -> ReadError {
+        ReadError::on_line(
+            field,
+            self.line_number,
+            ReadErrorKind::TooLarge {
+                value: value as u64,
+                max,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VisibleFaces;
+
+    fn read_text(text: &str) -> Result<VoxelModel, ReadError> {
+        read(Path::new("house.qef"), text.as_bytes())
+    }
+
+    #[test]
+    fn round_trips_a_matrix() {
+        let red = Color::rgb_u8(255, 0, 0);
+        let sky = Color::rgb_u8(40, 128, 255);
+
+        let mut matrix = Matrix::new(3, 2, 4);
+        matrix.set(Vec3::new(0.0, 0.0, 0.0), Voxel::Solid(red));
+        matrix.set(Vec3::new(2.0, 1.0, 3.0), Voxel::Solid(sky));
+        matrix.set(Vec3::new(1.0, 0.0, 2.0), Voxel::Solid(red));
+        let mut faces = VisibleFaces::TOP;
+        faces.insert(VisibleFaces::LEFT);
+        matrix.set_visible_faces(Vec3::new(1.0, 0.0, 2.0), faces);
+
+        let mut bytes = Vec::new();
+        write(&mut bytes, &matrix).unwrap();
+        let model = read(Path::new("house.qef"), &bytes).unwrap();
+
+        assert_eq!(model.name, "house");
+        assert_eq!(model.parts.len(), 1);
+
+        let part = &model.parts[0];
+        assert_eq!(part.matrix.dimensions(), (3, 2, 4));
+
+        let voxels: Vec<_> = part
+            .matrix
+            .solid_voxels()
+            .map(|(position, color)| (position, color_to_rgb_u8(color)))
+            .collect();
+        let expected: Vec<_> = matrix
+            .solid_voxels()
+            .map(|(position, color)| (position, color_to_rgb_u8(color)))
+            .collect();
+        assert_eq!(voxels, expected);
+
+        for (position, _) in matrix.solid_voxels() {
+            assert_eq!(
+                part.matrix.visible_faces(position),
+                matrix.visible_faces(position)
+            );
+        }
+    }
+
+    #[test]
+    fn skips_invisible_voxels() {
+        let model = read_text(
+            "Qubicle Exchange Format\nVersion 0.2\nwww.minddesk.com\n2 1 1\n1\n1 0 0\n\
+             0 0 0 0 0\n1 0 0 0 1\n",
+        )
+        .unwrap();
+
+        let matrix = &model.parts[0].matrix;
+        assert_eq!(*matrix.lookup(Vec3::zero()), Voxel::Empty);
+        assert_eq!(
+            *matrix.lookup(Vec3::new(1.0, 0.0, 0.0)),
+            Voxel::Solid(Color::rgb(1.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            matrix.visible_faces(Vec3::new(1.0, 0.0, 0.0)),
+            VisibleFaces::NONE
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_an_invalid_voxel() {
+        let error = read_text(
+            "Qubicle Exchange Format\nVersion 0.2\nwww.minddesk.com\n2 2 2\n1\n1 0 0\n\
+             0 0 0 0 1\n\n1 1 x 0 1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(error.field, "voxel");
+        assert!(error.is_line);
+        assert_eq!(error.offset, 9);
+        assert!(error.to_string().contains("on line 9"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_colour_indices_out_of_range() {
+        let error = read_text(
+            "Qubicle Exchange Format\nVersion 0.2\nwww.minddesk.com\n2 2 2\n1\n1 0 0\n\
+             0 0 0 1 1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(error.field, "voxel");
+        assert_eq!(error.offset, 7);
+    }
+
+    #[test]
+    fn rejects_voxels_outside_the_matrix() {
+        let error = read_text(
+            "Qubicle Exchange Format\nVersion 0.2\nwww.minddesk.com\n2 2 2\n1\n1 0 0\n\
+             0 2 0 0 1\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(error.field, "voxel");
+        assert_eq!(error.offset, 7);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let error = read_text("Qubicle Binary\n").unwrap_err();
+
+        assert_eq!(error.field, "header");
+        assert_eq!(error.offset, 1);
+
+        let error = read_text("Qubicle Exchange Format\nVersion 0.2\n").unwrap_err();
+
+        assert_eq!(error.field, "website");
+        assert_eq!(error.offset, 3);
+    }
+
+    #[test]
+    fn rejects_matrices_that_are_too_large() {
+        let error = read_text("Qubicle Exchange Format\nVersion 0.2\nwww.minddesk.com\n1 1 4096\n")
+            .unwrap_err();
+
+        assert_eq!(error.field, "matrix size");
+        assert_eq!(error.offset, 4);
+    }
+}
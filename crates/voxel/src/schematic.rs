@@ -0,0 +1,780 @@
+use crate::bytes::{ByteReader, ReadError, ReadErrorKind};
+use crate::math::{Color, Vec3};
+use crate::nbt::{self, Tag};
+use crate::{model_name, Matrix, Voxel, VoxelModel, VoxelModelPart};
+#[cfg(feature = "bevy")]
+use bevy::asset::AssetLoader;
+#[cfg(feature = "bevy")]
+use bevy::ecs::{FromResources, Resources};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+/// Loads Sponge `.schem` and legacy MCEdit `.schematic` files. Blocks are coloured with the
+/// `BlockColors` resource when the app has one, which has to be inserted before the plugin is
+/// added, and with the default table otherwise.
+#[cfg(feature = "bevy")]
+pub struct SchematicLoader {
+    colors: BlockColors,
+}
+
+#[cfg(feature = "bevy")]
+impl SchematicLoader {
+    pub fn new(colors: BlockColors) -> Self {
+        Self { colors }
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl FromResources for SchematicLoader {
+    fn from_resources(resources: &Resources) -> Self {
+        let colors = resources
+            .get::<BlockColors>()
+            .map(|colors| BlockColors::clone(&colors))
+            .unwrap_or_default();
+
+        Self::new(colors)
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl AssetLoader<VoxelModel> for SchematicLoader {
+    fn from_bytes(&self, path: &Path, bytes: Vec<u8>) -> anyhow::Result<VoxelModel, anyhow::Error> {
+        Ok(read_model(path, &bytes, &self.colors)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["schem", "schematic"];
+        EXTENSIONS
+    }
+}
+
+/// Maps Minecraft blocks to voxel colours. Blocks are looked up by their full block state, such
+/// as `minecraft:oak_log[axis=y]`, and then by their name without properties. Legacy schematics
+/// look up `id:data` and then `id`, such as `35:14` and then `35`.
+#[derive(Debug, Clone)]
+pub struct BlockColors {
+    colors: HashMap<String, Color>,
+    /// The colour of blocks that are not in the table. When `None` reading a schematic with such
+    /// blocks fails with a list of them.
+    pub unknown: Option<Color>,
+}
+
+impl BlockColors {
+    pub fn empty() -> Self {
+        Self {
+            colors: HashMap::new(),
+            unknown: None,
+        }
+    }
+
+    pub fn insert(&mut self, block: &str, color: Color) {
+        self.colors.insert(block.to_string(), color);
+    }
+
+    /// Adds every colour in `other`, replacing the colours of blocks that are in both.
+    pub fn extend(&mut self, other: BlockColors) {
+        self.colors.extend(other.colors);
+        self.unknown = other.unknown.or(self.unknown);
+    }
+
+    /// Parses a table with one block per line followed by a hex colour, such as
+    /// `minecraft:stone 7d7d7d`. A block of `*` sets the colour of unknown blocks. Blank lines and
+    /// lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut colors = Self::empty();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (block, hex) = match (parts.next(), parts.next()) {
+                (Some(block), Some(hex)) => (block, hex),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "expected a block and colour on line {}",
+                        number + 1
+                    ))
+                }
+            };
+
+            let rgb = u32::from_str_radix(hex.trim_start_matches('#'), 16)
+                .ok()
+                .filter(|_| hex.trim_start_matches('#').len() == 6)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("invalid colour {:?} on line {}", hex, number + 1)
+                })?;
+
+            let [_, r, g, b] = rgb.to_be_bytes();
+            let color = Color::rgb_u8(r, g, b);
+
+            if block == "*" {
+                colors.unknown = Some(color);
+            } else {
+                colors.insert(block, color);
+            }
+        }
+
+        Ok(colors)
+    }
+
+    /// The colour of a block, or `None` if the block is air or not in the table.
+    pub fn lookup(&self, block: &str) -> Option<Color> {
+        match self.block(block) {
+            Block::Solid(color) => Some(color),
+            Block::Air | Block::Unmapped => None,
+        }
+    }
+
+    fn block(&self, block: &str) -> Block {
+        let base = if block.starts_with(|c: char| c.is_ascii_digit()) {
+            block.split(':').next()
+        } else {
+            block.split('[').next()
+        }
+        .unwrap_or(block);
+
+        if AIR_BLOCKS.contains(&base) {
+            return Block::Air;
+        }
+
+        match self
+            .colors
+            .get(block)
+            .or_else(|| self.colors.get(base))
+            .copied()
+            .or(self.unknown)
+        {
+            Some(color) => Block::Solid(color),
+            None => Block::Unmapped,
+        }
+    }
+}
+
+/// What a block turns into once it has been looked up.
+#[derive(Debug, Copy, Clone)]
+enum Block {
+    Air,
+    Solid(Color),
+    /// A block with no colour in the table.
+    Unmapped,
+}
+
+const AIR_BLOCKS: &[&str] = &[
+    "0",
+    "minecraft:air",
+    "minecraft:cave_air",
+    "minecraft:void_air",
+    "minecraft:structure_void",
+];
+
+impl Default for BlockColors {
+    fn default() -> Self {
+        Self::parse(DEFAULT_BLOCK_COLORS).unwrap()
+    }
+}
+
+const DEFAULT_BLOCK_COLORS: &str = "
+minecraft:stone 7d7d7d
+minecraft:granite 956756
+minecraft:diorite bcbcbc
+minecraft:andesite 888888
+minecraft:grass_block 5d8f3a
+minecraft:dirt 866043
+minecraft:coarse_dirt 77553b
+minecraft:cobblestone 7a7a7a
+minecraft:oak_planks a2834f
+minecraft:spruce_planks 735531
+minecraft:birch_planks c0af79
+minecraft:bedrock 555555
+minecraft:water 3f76e4
+minecraft:lava cf5b13
+minecraft:sand dbcfa3
+minecraft:gravel 837f7e
+minecraft:oak_log 6d5533
+minecraft:oak_leaves 4a7a28
+minecraft:glass c0d8dc
+minecraft:sandstone d8cb9b
+minecraft:white_wool e9ecec
+minecraft:gray_wool 3e4447
+minecraft:red_wool a12722
+minecraft:bricks 966153
+minecraft:stone_bricks 7a7a7a
+minecraft:smooth_stone 9f9f9f
+minecraft:white_concrete cfd5d6
+minecraft:gray_concrete 373a3e
+minecraft:black_concrete 080a0f
+minecraft:terracotta 985e43
+minecraft:snow_block f9fefe
+minecraft:ice 91b7fd
+minecraft:clay a0a6b3
+minecraft:iron_block dcdcdc
+minecraft:quartz_block ebe5de
+1 7d7d7d
+2 5d8f3a
+3 866043
+4 7a7a7a
+5 a2834f
+7 555555
+8 3f76e4
+9 3f76e4
+10 cf5b13
+11 cf5b13
+12 dbcfa3
+13 837f7e
+17 6d5533
+18 4a7a28
+20 c0d8dc
+24 d8cb9b
+35 e9ecec
+35:7 3e4447
+35:14 a12722
+42 dcdcdc
+43 9f9f9f
+45 966153
+80 f9fefe
+82 a0a6b3
+98 7a7a7a
+155 ebe5de
+159 985e43
+172 985e43
+";
+
+/// Reads a schematic into a model with a single part named after the file.
+pub fn read_model(
+    path: &Path,
+    bytes: &[u8],
+    colors: &BlockColors,
+) -> Result<VoxelModel, ReadError> {
+    let name = model_name(path);
+    let matrix = read(bytes, colors)?;
+
+    let mut model = VoxelModel::new(name.clone());
+    model
+        .parts
+        .push(VoxelModelPart::new(name, matrix, Vec3::zero()));
+
+    Ok(model)
+}
+
+/// Reads a Sponge or legacy MCEdit schematic into a matrix, where Minecraft's Y up axes match our
+/// own. Blocks with no colour in `colors` are an error that lists them.
+pub fn read(bytes: &[u8], colors: &BlockColors) -> Result<Matrix, ReadError> {
+    let (_, root) = nbt::read(bytes)?;
+
+    // Version 3 Sponge schematics wrap everything in a `Schematic` compound.
+    let root = root.get("Schematic").unwrap_or(&root);
+
+    let width = dimension(root, "Width")?;
+    let height = dimension(root, "Height")?;
+    let length = dimension(root, "Length")?;
+
+    let volume = width * height * length;
+    if volume > Matrix::MAX_VOLUME {
+        return Err(ReadError::new(
+            "schematic size",
+            0,
+            ReadErrorKind::TooLarge {
+                value: volume as u64,
+                max: Matrix::MAX_VOLUME,
+            },
+        ));
+    }
+
+    let mut matrix = Matrix::new(width, height, length);
+
+    let unmapped = if root.get("Materials").is_some() {
+        legacy_blocks(root, colors, &mut matrix)?
+    } else {
+        sponge_blocks(root, colors, &mut matrix)?
+    };
+
+    if !unmapped.is_empty() {
+        return Err(unmapped_error(unmapped));
+    }
+
+    Ok(matrix)
+}
+
+fn dimension(root: &Tag, name: &'static str) -> Result<usize, ReadError> {
+    let value = root
+        .get(name)
+        .and_then(Tag::as_i64)
+        .ok_or_else(|| missing(name))?;
+
+    // Dimensions are stored as signed shorts but are really unsigned.
+    let value = (value as u16) as usize;
+
+    if value > Matrix::MAX_SIZE {
+        return Err(ReadError::new(
+            name,
+            0,
+            ReadErrorKind::TooLarge {
+                value: value as u64,
+                max: Matrix::MAX_SIZE,
+            },
+        ));
+    }
+
+    Ok(value)
+}
+
+/// Legacy schematics store a numeric block id and data value for each block. Ids above 255 keep
+/// their top four bits in `AddBlocks`, two blocks to a byte with the first in the high nibble.
+/// Returns the blocks that have no colour along with how many of each there are.
+fn legacy_blocks(
+    root: &Tag,
+    colors: &BlockColors,
+    matrix: &mut Matrix,
+) -> Result<Vec<(String, usize)>, ReadError> {
+    let volume = volume(matrix);
+
+    let ids = root
+        .get("Blocks")
+        .and_then(Tag::as_bytes)
+        .ok_or_else(|| missing("Blocks"))?;
+    let data = root.get("Data").and_then(Tag::as_bytes).unwrap_or(&[]);
+
+    if ids.len() < volume {
+        return Err(too_short("Blocks", ids.len(), volume));
+    }
+
+    let add_blocks = match root.get("AddBlocks") {
+        Some(tag) => tag.as_bytes().ok_or_else(|| missing("AddBlocks"))?,
+        None => &[],
+    };
+
+    if !add_blocks.is_empty() && add_blocks.len() * 2 < volume {
+        return Err(too_short("AddBlocks", add_blocks.len() * 2, volume));
+    }
+
+    // Each id and data pair is looked up once, as schematics only use a handful of them.
+    let mut blocks: HashMap<(u16, u8), (Block, usize)> = HashMap::new();
+
+    for (index, id) in ids[..volume].iter().enumerate() {
+        let add = match add_blocks.get(index / 2) {
+            Some(add) if index % 2 == 0 => add >> 4,
+            Some(add) => add & 0x0f,
+            None => 0,
+        };
+        let id = u16::from(add) << 8 | u16::from(*id);
+        let data = data.get(index).copied().unwrap_or(0);
+
+        let (block, count) = blocks
+            .entry((id, data))
+            .or_insert_with(|| (colors.block(&legacy_name(id, data)), 0));
+        *count += 1;
+
+        if let Block::Solid(color) = block {
+            set_block(matrix, index, *color);
+        }
+    }
+
+    Ok(blocks
+        .into_iter()
+        .filter(|(_, (block, _))| matches!(block, Block::Unmapped))
+        .map(|((id, data), (_, count))| (legacy_name(id, data), count))
+        .collect())
+}
+
+fn legacy_name(id: u16, data: u8) -> String {
+    if data == 0 {
+        id.to_string()
+    } else {
+        format!("{}:{}", id, data)
+    }
+}
+
+/// Sponge schematics store a palette of block states and a varint index into it for each block.
+/// Returns the blocks that have no colour along with how many of each there are.
+fn sponge_blocks(
+    root: &Tag,
+    colors: &BlockColors,
+    matrix: &mut Matrix,
+) -> Result<Vec<(String, usize)>, ReadError> {
+    let volume = volume(matrix);
+
+    // Version 3 moved the palette and block data into a `Blocks` compound.
+    let (palette, data) = match root.get("Blocks") {
+        Some(blocks) => (blocks.get("Palette"), blocks.get("Data")),
+        None => (root.get("Palette"), root.get("BlockData")),
+    };
+
+    let palette = match palette {
+        Some(Tag::Compound(palette)) => palette,
+        _ => return Err(missing("Palette")),
+    };
+    let data = data
+        .and_then(Tag::as_bytes)
+        .ok_or_else(|| missing("BlockData"))?;
+
+    // The block state of each palette index, where indices that no block state uses are `None`.
+    let mut palette_blocks: Vec<Option<(&str, Block)>> = vec![None; palette.len()];
+    let mut counts = vec![0; palette.len()];
+
+    for (name, index) in palette.iter() {
+        let slot = index
+            .as_i64()
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| palette_blocks.get_mut(index))
+            .ok_or_else(|| {
+                ReadError::new(
+                    "Palette",
+                    0,
+                    ReadErrorKind::Invalid(format!("{} has an invalid index", name)),
+                )
+            })?;
+
+        *slot = Some((name.as_str(), colors.block(name)));
+    }
+
+    let mut reader = ByteReader::new(data);
+
+    for index in 0..volume {
+        let offset = reader.offset();
+        let palette_index = read_varint(&mut reader)?;
+
+        let (palette_index, block) = usize::try_from(palette_index)
+            .ok()
+            .and_then(|index| match palette_blocks.get(index) {
+                Some(Some((_, block))) => Some((index, *block)),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                ReadError::new(
+                    "BlockData",
+                    offset,
+                    ReadErrorKind::Invalid(format!(
+                        "palette index {} does not exist",
+                        palette_index
+                    )),
+                )
+            })?;
+
+        counts[palette_index] += 1;
+
+        if let Block::Solid(color) = block {
+            set_block(matrix, index, color);
+        }
+    }
+
+    Ok(palette_blocks
+        .iter()
+        .zip(counts)
+        .filter_map(|(block, count)| match block {
+            Some((name, Block::Unmapped)) if count > 0 => Some((name.to_string(), count)),
+            _ => None,
+        })
+        .collect())
+}
+
+fn volume(matrix: &Matrix) -> usize {
+    let (width, height, length) = matrix.dimensions();
+    width * height * length
+}
+
+/// Blocks are stored X first, then Z, then Y.
+fn set_block(matrix: &mut Matrix, index: usize, color: Color) {
+    let (width, _, length) = matrix.dimensions();
+
+    let x = index % width;
+    let z = (index / width) % length;
+    let y = index / (width * length);
+
+    matrix.set(Vec3::new(x as f32, y as f32, z as f32), Voxel::Solid(color));
+}
+
+fn read_varint(reader: &mut ByteReader) -> Result<i64, ReadError> {
+    let mut value = 0i64;
+
+    for shift in (0..35).step_by(7) {
+        let byte = reader.read_u8("BlockData")?;
+        value |= i64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ReadError::new(
+        "BlockData",
+        reader.offset(),
+        ReadErrorKind::Invalid("varint is too long".to_string()),
+    ))
+}
+
+/// Lists the blocks with no colour, most common first, so they can be added to the table.
+fn unmapped_error(mut unmapped: Vec<(String, usize)>) -> ReadError {
+    const MAX_LISTED: usize = 10;
+
+    unmapped.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then(a_name.cmp(b_name))
+    });
+
+    let total: usize = unmapped.iter().map(|(_, count)| count).sum();
+    let mut listed: Vec<_> = unmapped
+        .iter()
+        .take(MAX_LISTED)
+        .map(|(name, count)| format!("{} ({})", name, count))
+        .collect();
+
+    if unmapped.len() > MAX_LISTED {
+        listed.push(format!("and {} more", unmapped.len() - MAX_LISTED));
+    }
+
+    ReadError::new(
+        "blocks",
+        0,
+        ReadErrorKind::Invalid(format!(
+            "{} blocks have no colour: {}",
+            total,
+            listed.join(", ")
+        )),
+    )
+}
+
+fn missing(field: &'static str) -> ReadError {
+    ReadError::new(field, 0, ReadErrorKind::Invalid("missing tag".to_string()))
+}
+
+fn too_short(field: &'static str, len: usize, expected: usize) -> ReadError {
+    ReadError::new(
+        field,
+        0,
+        ReadErrorKind::Invalid(format!("expected {} blocks but found {}", expected, len)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::tests::{compound, gzip, write};
+
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 2;
+    const LENGTH: usize = 2;
+    const VOLUME: usize = WIDTH * HEIGHT * LENGTH;
+
+    fn color(hex: u32) -> Voxel {
+        let [_, r, g, b] = hex.to_be_bytes();
+        Voxel::Solid(Color::rgb_u8(r, g, b))
+    }
+
+    fn voxel(matrix: &Matrix, x: usize, y: usize, z: usize) -> Voxel {
+        *matrix.lookup(Vec3::new(x as f32, y as f32, z as f32))
+    }
+
+    fn block_index(x: usize, y: usize, z: usize) -> usize {
+        x + z * WIDTH + y * WIDTH * LENGTH
+    }
+
+    fn dimensions() -> Vec<(&'static str, Tag)> {
+        vec![
+            ("Width", Tag::Short(WIDTH as i16)),
+            ("Height", Tag::Short(HEIGHT as i16)),
+            ("Length", Tag::Short(LENGTH as i16)),
+        ]
+    }
+
+    /// A palette with a stone floor, one oak log on top and air elsewhere. The log uses an index
+    /// above 127 so it takes two bytes as a varint, with unused entries padding the palette.
+    fn sponge_palette() -> (Tag, Vec<u8>) {
+        let mut palette = vec![
+            ("minecraft:air".to_string(), Tag::Int(0)),
+            ("minecraft:stone".to_string(), Tag::Int(1)),
+            ("minecraft:oak_log[axis=y]".to_string(), Tag::Int(200)),
+        ];
+        for index in 2..200 {
+            palette.push((format!("test:unused_{}", index), Tag::Int(index)));
+        }
+
+        let mut data = Vec::new();
+        for index in 0..VOLUME {
+            if index == block_index(1, 1, 0) {
+                data.extend_from_slice(&[200 | 0x80, 1]);
+            } else if index < WIDTH * LENGTH {
+                data.push(1);
+            } else {
+                data.push(0);
+            }
+        }
+
+        (Tag::Compound(palette.into_iter().collect()), data)
+    }
+
+    fn sponge_v2(palette: Tag, data: Vec<u8>) -> Vec<u8> {
+        let mut tags = dimensions();
+        tags.push(("Version", Tag::Int(2)));
+        tags.push(("Palette", palette));
+        tags.push(("BlockData", Tag::ByteArray(data)));
+
+        gzip(&write("Schematic", &compound(tags)))
+    }
+
+    fn sponge_v3(palette: Tag, data: Vec<u8>) -> Vec<u8> {
+        let mut tags = dimensions();
+        tags.push(("Version", Tag::Int(3)));
+        tags.push((
+            "Blocks",
+            compound(vec![("Palette", palette), ("Data", Tag::ByteArray(data))]),
+        ));
+
+        gzip(&write("", &compound(vec![("Schematic", compound(tags))])))
+    }
+
+    fn legacy(blocks: Vec<u8>, data: Vec<u8>, add_blocks: Option<Vec<u8>>) -> Vec<u8> {
+        let mut tags = dimensions();
+        tags.push(("Materials", Tag::String("Alpha".to_string())));
+        tags.push(("Blocks", Tag::ByteArray(blocks)));
+        tags.push(("Data", Tag::ByteArray(data)));
+        if let Some(add_blocks) = add_blocks {
+            tags.push(("AddBlocks", Tag::ByteArray(add_blocks)));
+        }
+
+        gzip(&write("Schematic", &compound(tags)))
+    }
+
+    fn assert_floor_and_log(matrix: &Matrix) {
+        assert_eq!(matrix.dimensions(), (WIDTH, HEIGHT, LENGTH));
+
+        for z in 0..LENGTH {
+            for x in 0..WIDTH {
+                assert_eq!(voxel(matrix, x, 0, z), color(0x7d7d7d));
+
+                if (x, z) == (1, 0) {
+                    assert_eq!(voxel(matrix, x, 1, z), color(0x6d5533));
+                } else {
+                    assert_eq!(voxel(matrix, x, 1, z), Voxel::Empty);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_sponge_version_2() {
+        let (palette, data) = sponge_palette();
+        let matrix = read(&sponge_v2(palette, data), &BlockColors::default()).unwrap();
+
+        assert_floor_and_log(&matrix);
+    }
+
+    #[test]
+    fn reads_sponge_version_3() {
+        let (palette, data) = sponge_palette();
+        let matrix = read(&sponge_v3(palette, data), &BlockColors::default()).unwrap();
+
+        assert_floor_and_log(&matrix);
+    }
+
+    #[test]
+    fn reads_legacy_ids_and_data() {
+        let mut blocks = vec![0; VOLUME];
+        let mut data = vec![0; VOLUME];
+        blocks[..WIDTH * LENGTH].copy_from_slice(&[1; WIDTH * LENGTH]);
+        blocks[block_index(1, 1, 0)] = 17;
+        // Red wool has its own colour, while other data values fall back to white wool.
+        blocks[block_index(0, 1, 1)] = 35;
+        data[block_index(0, 1, 1)] = 14;
+        blocks[block_index(2, 1, 1)] = 35;
+        data[block_index(2, 1, 1)] = 3;
+
+        let matrix = read(&legacy(blocks, data, None), &BlockColors::default()).unwrap();
+
+        assert_eq!(voxel(&matrix, 0, 0, 0), color(0x7d7d7d));
+        assert_eq!(voxel(&matrix, 1, 1, 0), color(0x6d5533));
+        assert_eq!(voxel(&matrix, 0, 1, 1), color(0xa12722));
+        assert_eq!(voxel(&matrix, 2, 1, 1), color(0xe9ecec));
+        assert_eq!(voxel(&matrix, 0, 1, 0), Voxel::Empty);
+    }
+
+    #[test]
+    fn reads_legacy_add_blocks() {
+        let mut blocks = vec![0; VOLUME];
+        let mut add_blocks = vec![0; VOLUME / 2];
+        // Block 0 is in the high nibble and block 1 in the low nibble of the first byte.
+        blocks[0] = 0x2c;
+        blocks[1] = 1;
+        add_blocks[0] = 0x10;
+
+        let mut colors = BlockColors::empty();
+        colors.insert("300", Color::rgb_u8(1, 2, 3));
+        colors.insert("1", Color::rgb_u8(4, 5, 6));
+
+        let matrix = read(&legacy(blocks, vec![0; VOLUME], Some(add_blocks)), &colors).unwrap();
+
+        assert_eq!(voxel(&matrix, 0, 0, 0), color(0x010203));
+        assert_eq!(voxel(&matrix, 1, 0, 0), color(0x040506));
+    }
+
+    #[test]
+    fn reports_blocks_without_a_colour() {
+        let mut blocks = vec![0; VOLUME];
+        blocks[..3].copy_from_slice(&[1, 222, 222]);
+        let bytes = legacy(blocks, vec![0; VOLUME], None);
+
+        let error = read(&bytes, &BlockColors::default()).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("2 blocks have no colour: 222 (2)"),
+            "{}",
+            error
+        );
+
+        let mut colors = BlockColors::default();
+        colors.extend(BlockColors::parse("* ff00ff").unwrap());
+        let matrix = read(&bytes, &colors).unwrap();
+
+        assert_eq!(voxel(&matrix, 1, 0, 0), color(0xff00ff));
+    }
+
+    #[test]
+    fn rejects_missing_palettes_and_blocks() {
+        let (_, data) = sponge_palette();
+        let mut tags = dimensions();
+        tags.push(("BlockData", Tag::ByteArray(data)));
+
+        let error = read(&write("", &compound(tags)), &BlockColors::default()).unwrap_err();
+        assert_eq!(error.field, "Palette");
+
+        let mut tags = dimensions();
+        tags.push(("Materials", Tag::String("Alpha".to_string())));
+
+        let error = read(&write("", &compound(tags)), &BlockColors::default()).unwrap_err();
+        assert_eq!(error.field, "Blocks");
+    }
+
+    #[test]
+    fn rejects_short_block_data() {
+        let (palette, mut data) = sponge_palette();
+        data.pop();
+
+        let error = read(&sponge_v2(palette, data), &BlockColors::default()).unwrap_err();
+        assert_eq!(error.field, "BlockData");
+
+        let error = read(
+            &legacy(vec![0; VOLUME - 1], Vec::new(), None),
+            &BlockColors::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.field, "Blocks");
+
+        let error = read(
+            &legacy(vec![0; VOLUME], Vec::new(), Some(vec![0; 2])),
+            &BlockColors::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.field, "AddBlocks");
+    }
+
+    #[test]
+    fn rejects_palette_indices_that_do_not_exist() {
+        let (_, data) = sponge_palette();
+        let palette = compound(vec![("minecraft:stone", Tag::Int(1))]);
+
+        assert!(read(&sponge_v2(palette, data), &BlockColors::default()).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::math::{Color, Vec3};
+use crate::{color_to_rgb_u8, Matrix, Voxel};
+use image::{Rgba, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+/// The axis that a directory of image slices is stacked along.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliceAxis {
+    /// Each image is a horizontal layer, viewed from above, with the first image at the bottom.
+    Y,
+    /// Each image is a vertical layer, viewed from the front, with the first image at the front.
+    Z,
+}
+
+/// Reads every `.png` in a directory, in file name order, as a slice of a matrix. As with Qubicle
+/// Binary files, pixels with an alpha of 0 are empty.
+pub fn read_dir(dir: &Path, axis: SliceAxis) -> anyhow::Result<Matrix> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+
+    paths.retain(
+        |path| matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("png")),
+    );
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!("no .png slices found in {}", dir.display()));
+    }
+
+    let mut slices = Vec::with_capacity(paths.len());
+    for path in paths.iter() {
+        let slice = image::open(path)
+            .map_err(|error| anyhow::anyhow!("failed to read {}: {}", path.display(), error))?
+            .to_rgba8();
+
+        slices.push(slice);
+    }
+
+    let (width, height) = slices[0].dimensions();
+    let (width, height) = (width as usize, height as usize);
+
+    for (path, slice) in paths.iter().zip(slices.iter()) {
+        if slice.dimensions() != slices[0].dimensions() {
+            return Err(anyhow::anyhow!(
+                "{} is {}x{} but the first slice is {}x{}",
+                path.display(),
+                slice.width(),
+                slice.height(),
+                width,
+                height
+            ));
+        }
+    }
+
+    let (size_x, size_y, size_z) = match axis {
+        SliceAxis::Y => (width, slices.len(), height),
+        SliceAxis::Z => (width, height, slices.len()),
+    };
+
+    if size_x.max(size_y).max(size_z) > Matrix::MAX_SIZE
+        || size_x * size_y * size_z > Matrix::MAX_VOLUME
+    {
+        return Err(anyhow::anyhow!(
+            "{}x{}x{} is too large for a matrix",
+            size_x,
+            size_y,
+            size_z
+        ));
+    }
+
+    let mut matrix = Matrix::new(size_x, size_y, size_z);
+
+    for (layer, slice) in slices.iter().enumerate() {
+        for (column, row, pixel) in slice.enumerate_pixels() {
+            let Rgba([r, g, b, a]) = *pixel;
+
+            if a == 0 {
+                continue;
+            }
+
+            let (x, y, z) = slice_position(axis, height, layer, column as usize, row as usize);
+
+            matrix.set(
+                Vec3::new(x as f32, y as f32, z as f32),
+                Voxel::Solid(Color::rgb_u8(r, g, b)),
+            );
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// Writes a matrix as numbered `.png` slices in a directory, creating it if needed. The slices can
+/// be read back with `read_dir`.
+pub fn write_dir(matrix: &Matrix, dir: &Path, axis: SliceAxis) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let (size_x, size_y, size_z) = matrix.dimensions();
+    let (width, height, layers) = match axis {
+        SliceAxis::Y => (size_x, size_z, size_y),
+        SliceAxis::Z => (size_x, size_y, size_z),
+    };
+
+    for layer in 0..layers {
+        let mut slice = RgbaImage::new(width as u32, height as u32);
+
+        for (column, row, pixel) in slice.enumerate_pixels_mut() {
+            let (x, y, z) = slice_position(axis, height, layer, column as usize, row as usize);
+
+            if let Voxel::Solid(color) = matrix.lookup(Vec3::new(x as f32, y as f32, z as f32)) {
+                let [r, g, b] = color_to_rgb_u8(*color);
+                *pixel = Rgba([r, g, b, u8::MAX]);
+            }
+        }
+
+        let path = dir.join(format!("{:04}.png", layer));
+        slice
+            .save(&path)
+            .map_err(|error| anyhow::anyhow!("failed to write {}: {}", path.display(), error))?;
+    }
+
+    Ok(())
+}
+
+/// The matrix position of a pixel. Vertical slices are flipped so that images appear upright.
+fn slice_position(
+    axis: SliceAxis,
+    height: usize,
+    layer: usize,
+    column: usize,
+    row: usize,
+) -> (usize, usize, usize) {
+    match axis {
+        SliceAxis::Y => (column, layer, row),
+        SliceAxis::Z => (column, height - 1 - row, layer),
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{VoxelModel, VoxelModelPart};
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+impl VoxelModel {
+    /// The transform of a top level part relative to the entity the model is spawned on. The part
+    /// is placed at its pivot so that rotating it turns it around that point.
+    pub fn part_transform(&self, part: &VoxelModelPart) -> Transform {
+        Transform::from_translation_rotation_scale(
+            (part.offset + part.pivot - self.pivot) * self.voxel_size,
+            Quat::identity(),
+            self.voxel_size,
+        )
+    }
+}
+
+/// A voxel model placed in the world. Once the `VoxelModel` has loaded, its mesh parts are spawned
+/// as children of the entity so that they all share its `Transform`.
+#[derive(Bundle)]
+pub struct VoxelModelComponents {
+    pub model: Handle<VoxelModel>,
+    pub transform: Transform,
+}
+
+impl VoxelModelComponents {
+    pub fn new(model: Handle<VoxelModel>) -> Self {
+        Self {
+            model,
+            transform: Default::default(),
+        }
+    }
+}
+
+/// Identifies the entity spawned for each part of a `VoxelModel` so that parts can be animated or
+/// hidden on their own.
+pub struct VoxelPart {
+    pub name: String,
+}
+
+/// Marks a mesh entity spawned as part of a `VoxelModel`.
+pub struct VoxelMesh {
+    pub model: Entity,
+}
+
+/// Marks an entity whose `VoxelModel` has had its parts spawned.
+pub struct VoxelModelSpawned;
+
+pub(crate) fn spawn_voxel_model_system(
+    mut commands: Commands,
+    models: Res<Assets<VoxelModel>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<Without<VoxelModelSpawned, (Entity, &Handle<VoxelModel>)>>,
+) {
+    for (entity, handle) in &mut query.iter() {
+        // The model may still be loading, in which case we try again next frame.
+        let model = match models.get(&handle) {
+            Some(model) => model,
+            None => continue,
+        };
+
+        let parts: Vec<Entity> = model
+            .parts
+            .iter()
+            .map(|part| {
+                spawn_part(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    entity,
+                    part,
+                    model.part_transform(part),
+                )
+            })
+            .collect();
+
+        commands
+            .push_children(entity, &parts)
+            .insert_one(entity, VoxelModelSpawned);
+    }
+}
+
+/// Despawns the parts of every entity whose `VoxelModel` has been reloaded, leaving the entity and
+/// its other components in place so `spawn_voxel_model_system` remeshes it from the new asset.
+/// The meshes and materials of the old parts are removed too, as nothing else uses them.
+pub(crate) fn respawn_modified_voxel_model_system(
+    mut commands: Commands,
+    mut event_reader: Local<EventReader<AssetEvent<VoxelModel>>>,
+    events: Res<Events<AssetEvent<VoxelModel>>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<With<VoxelModelSpawned, (Entity, &Handle<VoxelModel>, &Children)>>,
+    parts: Query<&VoxelPart>,
+    mut voxel_meshes: Query<(&VoxelMesh, &Handle<Mesh>, &Handle<StandardMaterial>)>,
+) {
+    let modified: HashSet<Handle<VoxelModel>> = event_reader
+        .iter(&events)
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(*handle),
+            _ => None,
+        })
+        .collect();
+
+    if modified.is_empty() {
+        return;
+    }
+
+    let mut respawned = HashSet::new();
+
+    for (entity, handle, children) in &mut query.iter() {
+        if !modified.contains(handle) {
+            continue;
+        }
+
+        // Only the parts we spawned are replaced, anything else parented to the model is kept.
+        for child in children.iter() {
+            if parts.get::<VoxelPart>(*child).is_ok() {
+                commands.despawn_recursive(*child);
+            }
+        }
+
+        commands.remove_one::<VoxelModelSpawned>(entity);
+        respawned.insert(entity);
+    }
+
+    for (voxel_mesh, mesh, material) in &mut voxel_meshes.iter() {
+        if respawned.contains(&voxel_mesh.model) {
+            meshes.remove(mesh);
+            materials.remove(material);
+        }
+    }
+}
+
+fn spawn_part(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    model: Entity,
+    part: &VoxelModelPart,
+    transform: Transform,
+) -> Entity {
+    let part_entity = commands
+        .spawn((
+            transform,
+            VoxelPart {
+                name: part.name.clone(),
+            },
+        ))
+        .current_entity()
+        .unwrap();
+
+    let mut children = Vec::new();
+
+    for (mesh, color) in part.matrix.mesh_parts() {
+        let mesh_entity = commands
+            .spawn(PbrComponents {
+                mesh: meshes.add(mesh.into()),
+                material: materials.add(StandardMaterial {
+                    albedo: color,
+                    ..Default::default()
+                }),
+                // Meshes start at the corner of the matrix rather than the part's pivot.
+                transform: Transform::from_translation(-part.pivot),
+                ..Default::default()
+            })
+            .with(VoxelMesh { model })
+            .current_entity()
+            .unwrap();
+
+        children.push(mesh_entity);
+    }
+
+    // Nested parts inherit the voxel scale of their parent so only need to be offset from it.
+    for child in part.children.iter() {
+        children.push(spawn_part(
+            commands,
+            meshes,
+            materials,
+            model,
+            child,
+            Transform::from_translation(child.offset + child.pivot - (part.offset + part.pivot)),
+        ));
+    }
+
+    commands.push_children(part_entity, &children);
+
+    part_entity
+}
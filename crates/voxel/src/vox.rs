@@ -0,0 +1,63 @@
+use crate::math::Color;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Voxel {
+    Empty,
+    Solid(Color),
+}
+
+impl PartialEq for Voxel {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (&Voxel::Empty, &Voxel::Empty) => true,
+            (&Voxel::Solid(a), &Voxel::Solid(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Converts a colour back to the 8 bit channels it is stored as in voxel files.
+pub fn color_to_rgb_u8(color: Color) -> [u8; 3] {
+    let to_u8 = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    [to_u8(color.r), to_u8(color.g), to_u8(color.b)]
+}
+
+/// The sides of a voxel that may have faces generated for them when meshing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VisibleFaces(u8);
+
+impl VisibleFaces {
+    pub const NONE: Self = Self(0);
+    pub const LEFT: Self = Self(1);
+    pub const RIGHT: Self = Self(1 << 1);
+    pub const TOP: Self = Self(1 << 2);
+    pub const BOTTOM: Self = Self(1 << 3);
+    pub const FRONT: Self = Self(1 << 4);
+    pub const BACK: Self = Self(1 << 5);
+    pub const ALL: Self = Self(0b11_1111);
+
+    /// The sides as a bit set, in the order of the constants above.
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// The inverse of `bits`. Bits that do not name a side are ignored.
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl Default for VisibleFaces {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
@@ -1,8 +1,15 @@
+use crate::cursor::CursorPosition;
+use crate::input::{Action, InputMap};
+use crate::save::Terrain;
 use bevy::input::mouse;
 use bevy::math;
 use bevy::prelude::{Plugin as BevyPlugin, *};
-use bevy_mod_picking::{PickingGroup, PickingMethod, PickingSource};
+use bevy::render::camera::{
+    Camera, CameraProjection, OrthographicProjection, PerspectiveProjection, WindowOrigin,
+};
+use bevy_mod_picking::{PickableMesh, PickingGroup, PickingMethod, PickingSource};
 use std::f32::consts::PI;
+use zville_voxel::{VoxelMesh, VoxelModel};
 
 pub const STARTUP_STAGE: &str = "camera_startup_stage";
 
@@ -14,10 +21,26 @@ impl BevyPlugin for Plugin {
             .add_startup_system_to_stage(STARTUP_STAGE, setup.system())
             .init_resource::<MoveSystemState>()
             .add_system(move_system.system())
+            .init_resource::<CameraModeSystemState>()
+            .add_system(camera_mode_system.system())
+            .init_resource::<KeyboardRotateSystemState>()
+            .add_system(keyboard_rotate_system.system())
+            .init_resource::<ZoomSettings>()
             .init_resource::<ZoomSystemState>()
             .add_system(zoom_system.system())
             .init_resource::<RotateSystemState>()
-            .add_system(rotate_system.system());
+            .add_system(rotate_system.system())
+            .init_resource::<CameraBounds>()
+            .add_system(terrain_bounds_system.system())
+            .add_system(select_target_system.system())
+            .add_system(follow_system.system())
+            .add_system(clamp_focus_system.system())
+            .add_system(camera_rig_system.system())
+            // Runs after bevy's own camera systems so the projection they set on resize is
+            // replaced in the same frame.
+            .add_system_to_stage(stage::POST_UPDATE, projection_system.system())
+            .add_system(cursor_picking_system.system())
+            .add_system(pickable_voxel_mesh_system.system());
     }
 }
 
@@ -25,45 +48,127 @@ pub struct CameraPickingGroup(pub PickingGroup);
 
 struct CameraComponent;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CameraMode {
+    /// Orbits the focus point freely with a perspective projection.
+    Perspective,
+    /// Looks down at the classic isometric angle with an orthographic projection, rotating in 90
+    /// degree steps.
+    Isometric,
+}
+
+/// Where the camera looks from. The camera orbits `focus` at `distance`, so moving, rotating and
+/// zooming each only change one part of the rig and `camera_rig_system` builds the transform.
+struct CameraRig {
+    focus: Vec3,
+    /// Rotation around the Y axis in radians.
+    yaw: f32,
+    /// Rotation around the camera's X axis in radians, negative when looking down.
+    pitch: f32,
+    distance: f32,
+    mode: CameraMode,
+    /// Height of the view in world units when the mode is `Isometric`.
+    ortho_height: f32,
+}
+
+impl CameraRig {
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(self.pitch)
+    }
+
+    fn translation(&self) -> Vec3 {
+        self.focus + self.rotation().mul_vec3(Vec3::unit_z()) * self.distance
+    }
+
+    /// The camera's right and forward directions flattened onto the ground, so that panning moves
+    /// at the same speed whatever the pitch.
+    fn ground_axes(&self) -> (Vec3, Vec3) {
+        let yaw = Quat::from_rotation_y(self.yaw);
+        (yaw.mul_vec3(Vec3::unit_x()), yaw.mul_vec3(-Vec3::unit_z()))
+    }
+
+    /// What the mouse wheel changes, which is the distance to the focus point in perspective and
+    /// the height of the view in isometric.
+    fn zoom(&self) -> f32 {
+        match self.mode {
+            CameraMode::Perspective => self.distance,
+            CameraMode::Isometric => self.ortho_height,
+        }
+    }
+
+    fn set_zoom(&mut self, zoom: f32) {
+        match self.mode {
+            CameraMode::Perspective => self.distance = zoom,
+            CameraMode::Isometric => self.ortho_height = zoom,
+        }
+    }
+
+    fn zoom_limits(&self) -> (f32, f32) {
+        match self.mode {
+            CameraMode::Perspective => (MIN_DISTANCE, MAX_DISTANCE),
+            CameraMode::Isometric => (MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT),
+        }
+    }
+}
+
+const STARTING_POSITION: [f32; 3] = [25.0, 20.0, 25.0];
 const STARTING_YAW: f32 = 45.0;
 const STARTING_PITCH: f32 = -15.0;
+const STARTING_DISTANCE: f32 = 20.0;
 
 fn setup(mut commands: Commands) {
     let picking_group = PickingGroup::Group(0);
 
+    let mut rig = CameraRig {
+        focus: Vec3::zero(),
+        yaw: STARTING_YAW.to_radians(),
+        pitch: STARTING_PITCH.to_radians(),
+        distance: STARTING_DISTANCE,
+        mode: CameraMode::Perspective,
+        ortho_height: STARTING_DISTANCE,
+    };
+    rig.focus =
+        Vec3::from(STARTING_POSITION) - rig.rotation().mul_vec3(Vec3::unit_z()) * rig.distance;
+
     commands
         .spawn(Camera3dComponents {
-            transform: Transform::from_translation_rotation(
-                Vec3::new(25.0, 20.0, 25.0),
-                Quat::from_rotation_ypr(
-                    STARTING_YAW.to_radians(),
-                    STARTING_PITCH.to_radians(),
-                    0.0,
-                ),
-            ),
+            transform: Transform::from_translation_rotation(rig.translation(), rig.rotation()),
             ..Default::default()
         })
         .with(CameraComponent)
-        .with(PickingSource::new(picking_group, PickingMethod::Center));
+        .with(rig)
+        .with(PickingSource::new(
+            picking_group,
+            PickingMethod::ScreenSpace(Vec2::zero()),
+        ));
 
     commands.insert_resource(CameraPickingGroup(picking_group));
 }
 
 const SCROLL_SPEED: f32 = 125.0;
 const SCROLL_MARGIN: f32 = 0.1;
+/// Speed of panning with the keyboard, in world units per second.
+const KEY_SCROLL_SPEED: f32 = 15.0;
 
 #[derive(Default)]
 struct MoveSystemState {
     cursor_moved_event_reader: EventReader<CursorMoved>,
     cursor_position: Vec2,
+    /// Whether the cursor has been outside the edge scroll margin since the camera started
+    /// following something. Clicking a model near the edge of the screen should not immediately
+    /// scroll away from it, so edge scrolling only takes over once the cursor returns to the edge.
+    edge_scroll_armed: bool,
 }
 
 fn move_system(
+    mut commands: Commands,
     mut state: ResMut<MoveSystemState>,
     events: Res<Events<CursorMoved>>,
     windows: Res<Windows>,
+    (keyboard_input, input_map): (Res<Input<KeyCode>>, Res<InputMap>),
     time: Res<Time>,
-    mut camera_query: Query<(&CameraComponent, &mut Transform)>,
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig)>,
+    mut following_query: Query<With<CameraTarget, (Entity, &CameraComponent)>>,
 ) {
     let window = windows.get_primary().unwrap();
     let screen_width = window.width as f32;
@@ -79,7 +184,9 @@ fn move_system(
     }
 
     let position = state.cursor_position;
-    let mut movement = Vec3::zero();
+    // Movement relative to the screen, X to the right and Y up.
+    let mut movement = Vec2::zero();
+    let mut key_movement = Vec2::zero();
 
     if position.x() < SCROLL_MARGIN {
         *movement.x_mut() -= (SCROLL_MARGIN - position.x()) * SCROLL_SPEED;
@@ -88,61 +195,281 @@ fn move_system(
     }
 
     if position.y() < SCROLL_MARGIN {
-        *movement.z_mut() += (SCROLL_MARGIN - position.y()) * SCROLL_SPEED;
+        *movement.y_mut() -= (SCROLL_MARGIN - position.y()) * SCROLL_SPEED;
     } else if position.y() > (1.0 - SCROLL_MARGIN) {
-        *movement.z_mut() -= (position.y() - (1.0 - SCROLL_MARGIN)) * SCROLL_SPEED;
+        *movement.y_mut() += (position.y() - (1.0 - SCROLL_MARGIN)) * SCROLL_SPEED;
+    }
+
+    let pressed = |action| input_map.pressed(action, &keyboard_input);
+
+    if pressed(Action::PanLeft) {
+        *key_movement.x_mut() -= KEY_SCROLL_SPEED;
+    }
+    if pressed(Action::PanRight) {
+        *key_movement.x_mut() += KEY_SCROLL_SPEED;
+    }
+    if pressed(Action::PanBack) {
+        *key_movement.y_mut() -= KEY_SCROLL_SPEED;
+    }
+    if pressed(Action::PanForward) {
+        *key_movement.y_mut() += KEY_SCROLL_SPEED;
+    }
+
+    let is_following = following_query.iter().iter().next().is_some();
+
+    if !is_following {
+        state.edge_scroll_armed = false;
+    } else if movement == Vec2::zero() {
+        state.edge_scroll_armed = true;
+    } else if !state.edge_scroll_armed {
+        movement = Vec2::zero();
     }
 
-    for (_, mut transform) in &mut camera_query.iter() {
+    movement += key_movement;
+
+    if movement == Vec2::zero() {
+        return;
+    }
+
+    // Panning takes back control from follow mode.
+    for (entity, _) in &mut following_query.iter() {
+        commands.remove_one::<CameraTarget>(entity);
+    }
+
+    for (_, mut rig) in &mut camera_query.iter() {
         // Account for camera direction.
-        movement = transform.rotation().mul_vec3(movement);
+        let (right, forward) = rig.ground_axes();
+        rig.focus += (right * movement.x() + forward * movement.y()) * time.delta_seconds;
+    }
+}
 
-        let translation = Vec3::new(
-            transform.translation().x() + movement.x() * time.delta_seconds,
-            transform.translation().y(),
-            transform.translation().z() + movement.z() * time.delta_seconds,
-        );
+/// The pitch of a classic isometric view, where the three axes appear equally foreshortened.
+fn isometric_pitch() -> f32 {
+    -(1.0 / 2.0f32.sqrt()).atan()
+}
+
+/// The isometric yaw nearest to `yaw`. Isometric views look along a diagonal of the grid, so they
+/// sit halfway between the axes.
+fn isometric_yaw(yaw: f32) -> f32 {
+    let step = PI / 2.0;
+    ((yaw - PI / 4.0) / step).round() * step + PI / 4.0
+}
+
+/// Isometric views are a long way from their focus point so nothing in view is behind the camera.
+const ISOMETRIC_DISTANCE: f32 = 200.0;
+const ISOMETRIC_FAR: f32 = 1000.0;
+
+#[derive(Default)]
+struct CameraModeSystemState {
+    /// The pitch and distance to go back to when leaving the isometric view.
+    perspective: Option<(f32, f32)>,
+}
+
+fn camera_mode_system(
+    mut state: ResMut<CameraModeSystemState>,
+    (keyboard_input, input_map): (Res<Input<KeyCode>>, Res<InputMap>),
+    (mut zoom_state, mut rotate_state): (
+        ResMut<ZoomSystemState>,
+        ResMut<KeyboardRotateSystemState>,
+    ),
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig, &PerspectiveProjection)>,
+) {
+    if !input_map.just_pressed(Action::ToggleIsometric, &keyboard_input) {
+        return;
+    }
 
-        transform.set_translation(translation);
+    // Zooming and rotating act on different values in each mode, so anything in progress stops.
+    zoom_state.target = None;
+    zoom_state.anchor = None;
+    rotate_state.target_yaw = None;
+
+    for (_, mut rig, perspective) in &mut camera_query.iter() {
+        match rig.mode {
+            CameraMode::Perspective => {
+                state.perspective = Some((rig.pitch, rig.distance));
+
+                // Start with about as much of the world in view as the perspective camera had at
+                // its focus point.
+                rig.ortho_height = math::clamp(
+                    2.0 * rig.distance * (perspective.fov / 2.0).tan(),
+                    MIN_ORTHO_HEIGHT,
+                    MAX_ORTHO_HEIGHT,
+                );
+                rig.mode = CameraMode::Isometric;
+                rig.pitch = isometric_pitch();
+                rig.distance = ISOMETRIC_DISTANCE;
+                rotate_state.target_yaw = Some(isometric_yaw(rig.yaw));
+            }
+            CameraMode::Isometric => {
+                let (pitch, distance) = state
+                    .perspective
+                    .take()
+                    .unwrap_or((STARTING_PITCH.to_radians(), STARTING_DISTANCE));
+
+                rig.mode = CameraMode::Perspective;
+                rig.pitch = pitch;
+                rig.distance = distance;
+            }
+        }
     }
 }
 
-const ZOOM_SPEED: f32 = 0.5;
-const MAX_ZOOM: f32 = 30.0;
-const MIN_ZOOM: f32 = 10.0;
+/// Speed of rotating with the keyboard, in radians per second.
+const KEY_ROTATE_SPEED: f32 = PI / 2.0;
+/// How quickly the isometric view turns to its next 90 degree step.
+const SNAP_ROTATE_SMOOTHING: f32 = 12.0;
+
+#[derive(Default)]
+struct KeyboardRotateSystemState {
+    /// The yaw an isometric view is turning to, until it gets there.
+    target_yaw: Option<f32>,
+}
+
+/// Rotates the camera around its focus point, smoothly in perspective and in animated 90 degree
+/// steps in isometric.
+fn keyboard_rotate_system(
+    mut state: ResMut<KeyboardRotateSystemState>,
+    (keyboard_input, input_map): (Res<Input<KeyCode>>, Res<InputMap>),
+    time: Res<Time>,
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig)>,
+) {
+    for (_, mut rig) in &mut camera_query.iter() {
+        match rig.mode {
+            CameraMode::Perspective => {
+                let mut rotation = 0.0;
+
+                if input_map.pressed(Action::RotateLeft, &keyboard_input) {
+                    rotation += KEY_ROTATE_SPEED;
+                }
+                if input_map.pressed(Action::RotateRight, &keyboard_input) {
+                    rotation -= KEY_ROTATE_SPEED;
+                }
+
+                rig.yaw += rotation * time.delta_seconds;
+            }
+            CameraMode::Isometric => {
+                // Steps queue up on the target so pressing quickly turns several steps.
+                let mut target = state.target_yaw.unwrap_or(rig.yaw);
+
+                if input_map.just_pressed(Action::RotateLeft, &keyboard_input) {
+                    target = isometric_yaw(target) + PI / 2.0;
+                }
+                if input_map.just_pressed(Action::RotateRight, &keyboard_input) {
+                    target = isometric_yaw(target) - PI / 2.0;
+                }
+
+                let remaining = target - rig.yaw;
+
+                if remaining.abs() < 0.001 {
+                    rig.yaw = target;
+                    state.target_yaw = None;
+                } else {
+                    rig.yaw +=
+                        remaining * (1.0 - (-SNAP_ROTATE_SMOOTHING * time.delta_seconds).exp());
+                    state.target_yaw = Some(target);
+                }
+            }
+        }
+    }
+}
+
+/// Limits on the distance from the camera to its focus point.
+const MIN_DISTANCE: f32 = 5.0;
+const MAX_DISTANCE: f32 = 60.0;
+/// Limits on the height of the isometric view in world units.
+const MIN_ORTHO_HEIGHT: f32 = 8.0;
+const MAX_ORTHO_HEIGHT: f32 = 80.0;
+
+/// How the mouse wheel zooms the camera.
+pub struct ZoomSettings {
+    /// World units zoomed per pixel scrolled.
+    pub speed: f32,
+    /// Pixels that one line of scrolling counts as. Most mice scroll in lines while touchpads
+    /// scroll in pixels.
+    pub pixels_per_line: f32,
+    /// How quickly the camera eases toward the zoom the wheel has asked for. Higher values feel
+    /// snappier, lower values glide for longer.
+    pub smoothing: f32,
+}
+
+impl Default for ZoomSettings {
+    fn default() -> Self {
+        Self {
+            speed: 0.1,
+            pixels_per_line: 20.0,
+            smoothing: 10.0,
+        }
+    }
+}
 
 #[derive(Default)]
 struct ZoomSystemState {
     mouse_wheel_event_reader: EventReader<mouse::MouseWheel>,
+    /// The zoom being eased toward, until it is reached.
+    target: Option<f32>,
+    /// The point being zoomed toward, or the focus point when nothing was picked.
+    anchor: Option<Vec3>,
 }
 
+/// Dollies the camera along the ray toward the picked voxel, or scales the isometric view around
+/// it, so whatever is under the pointer stays under it while zooming.
 fn zoom_system(
     mut state: ResMut<ZoomSystemState>,
+    settings: Res<ZoomSettings>,
     events: Res<Events<mouse::MouseWheel>>,
+    (pick_state, pick_group): (Res<bevy_mod_picking::PickState>, Res<CameraPickingGroup>),
     time: Res<Time>,
-    mut camera_query: Query<(&CameraComponent, &mut Transform)>,
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig)>,
 ) {
-    for event in state.mouse_wheel_event_reader.iter(&events) {
-        if let mouse::MouseScrollUnit::Pixel = event.unit {
-            for (_, mut transform) in &mut camera_query.iter() {
-                let current = transform.translation().y();
-                let movement = (event.y * ZOOM_SPEED) * time.delta_seconds;
-                let new_value = current + movement;
-
-                let mut translation = transform.translation();
-                translation.set_y(math::clamp(new_value, MIN_ZOOM, MAX_ZOOM));
-                transform.set_translation(translation);
-            }
+    for (_, mut rig) in &mut camera_query.iter() {
+        let current = rig.zoom();
+        let (min, max) = rig.zoom_limits();
+        let mut target = state.target.unwrap_or(current);
+
+        for event in state.mouse_wheel_event_reader.iter(&events) {
+            let pixels = match event.unit {
+                mouse::MouseScrollUnit::Line => event.y * settings.pixels_per_line,
+                mouse::MouseScrollUnit::Pixel => event.y,
+            };
+
+            // Scrolling up zooms in.
+            target = math::clamp(target - pixels * settings.speed, min, max);
+            state.anchor = pick_state.top(pick_group.0).map(|pick| *pick.position());
+        }
+
+        // Cover the same fraction of the remaining distance every second whatever the frame
+        // rate, which slows the camera down smoothly as it arrives.
+        let remaining = target - current;
+        let zoom = if remaining.abs() < 0.01 {
+            state.target = None;
+            target
         } else {
-            panic!("we currently only deal with pixel units on mouse scroll");
+            state.target = Some(target);
+            current + remaining * (1.0 - (-settings.smoothing * time.delta_seconds).exp())
+        };
+
+        // Scaling the rig about the anchor moves the camera along the ray toward it while keeping
+        // the focus point on the view ray. In isometric the same scale keeps the anchor at the
+        // same place on screen as the view shrinks around it.
+        let anchor = state.anchor.unwrap_or(rig.focus);
+        let scale = zoom / current;
+        rig.focus = anchor + (rig.focus - anchor) * scale;
+        rig.set_zoom(zoom);
+
+        if state.target.is_none() {
+            state.anchor = None;
         }
     }
 }
 
+/// Stops the camera from flipping over when orbiting past straight up or down.
+const MAX_PITCH: f32 = 89.0;
+
 #[derive(Default)]
 struct RotateSystemState {
     mouse_motion_event_reader: EventReader<mouse::MouseMotion>,
-    focus: Option<Vec3>,
+    /// The picked point being orbited around.
+    pivot: Option<Vec3>,
 }
 
 fn rotate_system(
@@ -151,8 +478,19 @@ fn rotate_system(
     (mouse_button_input, mouse_events): (Res<Input<MouseButton>>, Res<Events<mouse::MouseMotion>>),
     (pick_state, pick_group): (Res<bevy_mod_picking::PickState>, Res<CameraPickingGroup>),
     windows: Res<Windows>,
-    mut camera_query: Query<(&CameraComponent, &mut Transform)>,
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig)>,
 ) {
+    // The isometric view keeps its angle, only turning with the rotate keys.
+    let is_isometric = camera_query
+        .iter()
+        .iter()
+        .any(|(_, rig)| rig.mode == CameraMode::Isometric);
+
+    if is_isometric {
+        state.pivot = None;
+        return;
+    }
+
     if keyboard_input.pressed(KeyCode::LShift) && mouse_button_input.pressed(MouseButton::Left) {
         let mut rotation_move = Vec2::zero();
 
@@ -160,39 +498,273 @@ fn rotate_system(
             rotation_move -= event.delta;
         }
 
-        match state.focus {
-            Some(focus) => {
-                let window = windows.get_primary().unwrap();
-                let screen_width = window.width as f32;
-                let screen_height = window.height as f32;
+        if let Some(pivot) = state.pivot {
+            let window = windows.get_primary().unwrap();
+            let screen_width = window.width as f32;
+            let screen_height = window.height as f32;
+
+            // Link virtual sphere rotation relative to window to make it feel nicer
+            let delta_x = rotation_move.x() / screen_width * PI * 2.0;
+            let delta_y = rotation_move.y() / screen_height * PI;
+
+            for (_, mut rig) in &mut camera_query.iter() {
+                let pitch = math::clamp(
+                    rig.pitch + delta_y,
+                    -MAX_PITCH.to_radians(),
+                    MAX_PITCH.to_radians(),
+                );
+
+                // Swing the whole rig around the pivot so the point that was picked stays under
+                // the pointer rather than the view jumping to centre it.
+                let (right, _) = rig.ground_axes();
+                let rotation = Quat::from_rotation_y(delta_x)
+                    * Quat::from_axis_angle(right, pitch - rig.pitch);
+
+                rig.focus = pivot + rotation.mul_vec3(rig.focus - pivot);
+                rig.yaw += delta_x;
+                rig.pitch = pitch;
+            }
+        } else if let Some(pick) = pick_state.top(pick_group.0) {
+            state.pivot = Some(*pick.position());
+        }
+
+        return;
+    }
 
-                // Link virtual sphere rotation relative to window to make it feel nicer
-                let delta_x = rotation_move.x() / screen_width * PI * 2.0;
-                let delta_y = rotation_move.y() / screen_height * PI;
+    state.pivot = None;
+}
 
-                let delta_yaw = Quat::from_rotation_y(delta_x);
-                let delta_pitch = Quat::from_rotation_x(delta_y);
+/// Makes the camera follow an entity, keeping its zoom and yaw, until the player pans or cancels.
+/// Insert it on the camera entity to follow something from code.
+pub struct CameraTarget(pub Entity);
 
-                for (_, mut transform) in &mut camera_query.iter() {
-                    let translation =
-                        delta_yaw * delta_pitch * (transform.translation() - focus) + focus;
-                    transform.set_translation(translation);
+/// Marks voxel models that the camera follows when they are clicked, such as citizens, vehicles
+/// and buildings.
+pub struct Followable;
 
-                    let look = Mat4::face_toward(transform.translation(), focus, Vec3::unit_y());
-                    transform.set_rotation(look.to_scale_rotation_translation().1);
-                }
+fn select_target_system(
+    mut commands: Commands,
+    (keyboard_input, input_map): (Res<Input<KeyCode>>, Res<InputMap>),
+    mouse_button_input: Res<Input<MouseButton>>,
+    (pick_state, pick_group): (Res<bevy_mod_picking::PickState>, Res<CameraPickingGroup>),
+    voxel_meshes: Query<&VoxelMesh>,
+    followables: Query<&Followable>,
+    mut camera_query: Query<With<CameraComponent, Entity>>,
+) {
+    if input_map.just_pressed(Action::Cancel, &keyboard_input) {
+        for entity in &mut camera_query.iter() {
+            commands.remove_one::<CameraTarget>(entity);
+        }
+
+        return;
+    }
+
+    // Shift clicking orbits instead.
+    if !mouse_button_input.just_pressed(MouseButton::Left)
+        || keyboard_input.pressed(KeyCode::LShift)
+    {
+        return;
+    }
+
+    // Picks hit the mesh of one of the model's parts, so follow the model that owns it.
+    let model = match pick_state
+        .top(pick_group.0)
+        .and_then(|pick| voxel_meshes.get::<VoxelMesh>(pick.entity()).ok())
+    {
+        Some(voxel_mesh) => voxel_mesh.model,
+        None => return,
+    };
+
+    if followables.get::<Followable>(model).is_err() {
+        return;
+    }
+
+    for entity in &mut camera_query.iter() {
+        commands.insert_one(entity, CameraTarget(model));
+    }
+}
+
+/// How quickly the focus point catches up with a followed entity.
+const FOLLOW_SMOOTHING: f32 = 5.0;
+
+fn follow_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    targets: Query<&GlobalTransform>,
+    mut camera_query: Query<(Entity, &CameraTarget, &mut CameraRig)>,
+) {
+    for (entity, target, mut rig) in &mut camera_query.iter() {
+        let translation = match targets.get::<GlobalTransform>(target.0) {
+            Ok(transform) => transform.translation(),
+            // The target has been despawned.
+            Err(_) => {
+                commands.remove_one::<CameraTarget>(entity);
+                continue;
             }
-            None => {
-                if let Some(pick) = pick_state.top(pick_group.0) {
-                    state.focus = Some(*pick.position());
+        };
+
+        let step = 1.0 - (-FOLLOW_SMOOTHING * time.delta_seconds).exp();
+        rig.focus += (translation - rig.focus) * step;
+    }
+}
+
+/// The area of the XZ plane that the camera's focus point is kept over, or `None` when there is
+/// no terrain to keep it over.
+#[derive(Default)]
+pub struct CameraBounds {
+    pub area: Option<(Vec2, Vec2)>,
+}
+
+fn terrain_bounds_system(
+    models: Res<Assets<VoxelModel>>,
+    mut bounds: ResMut<CameraBounds>,
+    mut terrain_query: Query<With<Terrain, (&Handle<VoxelModel>, &Transform)>>,
+) {
+    let mut area: Option<(Vec2, Vec2)> = None;
+
+    for (handle, transform) in &mut terrain_query.iter() {
+        let model = match models.get(&handle) {
+            Some(model) => model,
+            None => continue,
+        };
+
+        let (min, max) = match model.extents() {
+            Some(extents) => extents,
+            None => continue,
+        };
+
+        let min = (min - model.pivot) * model.voxel_size;
+        let max = (max - model.pivot) * model.voxel_size;
+
+        // Transform each corner so that rotated or scaled terrain is still covered.
+        for corner in [
+            Vec3::new(min.x(), min.y(), min.z()),
+            Vec3::new(max.x(), min.y(), min.z()),
+            Vec3::new(min.x(), min.y(), max.z()),
+            Vec3::new(max.x(), min.y(), max.z()),
+        ]
+        .iter()
+        {
+            let corner = transform.value.transform_point3(*corner);
+            let corner = Vec2::new(corner.x(), corner.z());
+
+            area = Some(match area {
+                Some((area_min, area_max)) => (area_min.min(corner), area_max.max(corner)),
+                None => (corner, corner),
+            });
+        }
+    }
+
+    bounds.area = area;
+}
+
+/// How far, in world units, the focus point may be pushed past the edge of the terrain.
+const BOUNDS_MARGIN: f32 = 8.0;
+/// How quickly the focus point springs back inside the terrain once it is past the edge.
+const BOUNDS_SPRING: f32 = 6.0;
+
+/// Keeps the focus point over the terrain. The clamp happens in world space after panning has been
+/// rotated by the camera's yaw, so the edges hold at any rotation. Panning past an edge meets more
+/// and more resistance until the margin, and the focus drifts back once panning stops.
+fn clamp_focus_system(
+    bounds: Res<CameraBounds>,
+    time: Res<Time>,
+    mut camera_query: Query<(&CameraComponent, &mut CameraRig)>,
+) {
+    let (min, max) = match bounds.area {
+        Some(area) => area,
+        None => return,
+    };
+
+    let spring = 1.0 - (-BOUNDS_SPRING * time.delta_seconds).exp();
+
+    let soft_clamp = |value: f32, min: f32, max: f32| {
+        let value = math::clamp(value, min - BOUNDS_MARGIN, max + BOUNDS_MARGIN);
+        value + (math::clamp(value, min, max) - value) * spring
+    };
+
+    for (_, mut rig) in &mut camera_query.iter() {
+        let x = soft_clamp(rig.focus.x(), min.x(), max.x());
+        let z = soft_clamp(rig.focus.z(), min.y(), max.y());
+
+        rig.focus.set_x(x);
+        rig.focus.set_z(z);
+    }
+}
+
+fn camera_rig_system(mut camera_query: Query<(&CameraRig, &mut Transform)>) {
+    for (rig, mut transform) in &mut camera_query.iter() {
+        transform.set_translation(rig.translation());
+        transform.set_rotation(rig.rotation());
+    }
+}
+
+fn projection_system(
+    windows: Res<Windows>,
+    mut camera_query: Query<(&CameraRig, &PerspectiveProjection, &mut Camera)>,
+) {
+    for (rig, perspective, mut camera) in &mut camera_query.iter() {
+        camera.projection_matrix = match rig.mode {
+            CameraMode::Perspective => perspective.get_projection_matrix(),
+            CameraMode::Isometric => {
+                let window = match windows.get(camera.window) {
+                    Some(window) => window,
+                    None => continue,
+                };
+
+                let half_height = rig.ortho_height / 2.0;
+                let half_width = half_height * window.width as f32 / window.height as f32;
+
+                OrthographicProjection {
+                    left: -half_width,
+                    right: half_width,
+                    bottom: -half_height,
+                    top: half_height,
+                    near: 0.0,
+                    far: ISOMETRIC_FAR,
+                    window_origin: WindowOrigin::Center,
                 }
+                .get_projection_matrix()
             }
-        }
+        };
+    }
+}
 
+/// Picks from the software cursor rather than the OS cursor, which is locked to the window, so
+/// hovering, orbiting and zooming act on what is under the pointer.
+fn cursor_picking_system(
+    mut commands: Commands,
+    mut last_position: Local<Option<Vec2>>,
+    cursor_position: Res<CursorPosition>,
+    pick_group: Res<CameraPickingGroup>,
+    windows: Res<Windows>,
+    mut camera_query: Query<With<CameraComponent, (Entity, &PickingSource)>>,
+) {
+    if *last_position == Some(cursor_position.0) {
         return;
     }
 
-    if state.focus.is_some() {
-        state.focus = None;
+    let window = windows.get_primary().unwrap();
+    let screen_size = Vec2::new(window.width as f32, window.height as f32);
+    // The picking source takes normalised device coordinates, from -1 to 1 across the window.
+    let position = cursor_position.0 / screen_size * 2.0 - Vec2::one();
+
+    for (entity, _) in &mut camera_query.iter() {
+        commands.insert_one(
+            entity,
+            PickingSource::new(pick_group.0, PickingMethod::ScreenSpace(position)),
+        );
+    }
+
+    *last_position = Some(cursor_position.0);
+}
+
+fn pickable_voxel_mesh_system(
+    mut commands: Commands,
+    pick_group: Res<CameraPickingGroup>,
+    mut query: Query<Without<PickableMesh, (Entity, &VoxelMesh)>>,
+) {
+    for (entity, _) in &mut query.iter() {
+        commands.insert_one(entity, PickableMesh::new([pick_group.0].into()));
     }
 }
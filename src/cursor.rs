@@ -1,3 +1,4 @@
+use crate::loading::LoadingAssets;
 use crate::window::LockCursor;
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::{Plugin as BevyPlugin, *};
@@ -9,7 +10,8 @@ pub struct Plugin;
 
 impl BevyPlugin for Plugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<LockCursorSystemState>()
+        app.init_resource::<CursorPosition>()
+            .init_resource::<LockCursorSystemState>()
             .add_startup_system(setup.system())
             .add_system(lock_cursor_system.system())
             .add_stage_before(stage::EVENT_UPDATE, STAGE)
@@ -24,6 +26,11 @@ impl BevyPlugin for Plugin {
     }
 }
 
+/// Where the software cursor is, in pixels from the bottom left of the primary window. The OS
+/// cursor is locked and hidden, so this is what the player is pointing at.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CursorPosition(pub Vec2);
+
 struct CursorComponent {
     size: Size,
 }
@@ -31,10 +38,13 @@ struct CursorComponent {
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     commands.spawn(UiCameraComponents::default());
 
+    let texture: Handle<Texture> = loading.load(&asset_server, "assets/cursor.png");
+
     let cursor_component = CursorComponent {
         size: Size {
             width: 14.0,
@@ -58,7 +68,7 @@ fn setup(
                 },
                 ..Default::default()
             },
-            material: materials.add(asset_server.load("assets/cursor.png").unwrap().into()),
+            material: materials.add(texture.into()),
             draw: Draw {
                 is_transparent: true,
                 ..Default::default()
@@ -99,6 +109,7 @@ fn publish_cursor_moved_events_within_bounds_system(
     mut state: ResMut<PublishCursorMovedSystemState>,
     mouse_events: Res<Events<MouseMotion>>,
     mut cursor_moved_events: ResMut<Events<CursorMoved>>,
+    mut cursor_position: ResMut<CursorPosition>,
     windows: Res<Windows>,
 ) {
     let window = windows.get_primary().unwrap();
@@ -137,6 +148,7 @@ fn publish_cursor_moved_events_within_bounds_system(
     });
 
     state.last_cursor_pos = state.cursor_pos;
+    cursor_position.0 = state.cursor_pos;
 }
 
 #[derive(Default)]
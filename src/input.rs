@@ -0,0 +1,76 @@
+use bevy::prelude::{Plugin as BevyPlugin, *};
+use std::collections::HashMap;
+
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<InputMap>();
+    }
+}
+
+/// Something the player can do from the keyboard.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    PanForward,
+    PanBack,
+    PanLeft,
+    PanRight,
+    RotateLeft,
+    RotateRight,
+    ToggleIsometric,
+    QuickSave,
+    QuickLoad,
+    /// Backs out of the current mode, such as following an entity, or quits when there is
+    /// nothing to back out of.
+    Cancel,
+}
+
+/// The keys bound to each action. Actions may have several keys so that, for example, WASD and
+/// the arrow keys both pan; rebinding replaces them for left handed players or AZERTY layouts.
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = Self {
+            bindings: HashMap::new(),
+        };
+
+        map.bind(Action::PanForward, &[KeyCode::W, KeyCode::Up]);
+        map.bind(Action::PanBack, &[KeyCode::S, KeyCode::Down]);
+        map.bind(Action::PanLeft, &[KeyCode::A, KeyCode::Left]);
+        map.bind(Action::PanRight, &[KeyCode::D, KeyCode::Right]);
+        map.bind(Action::RotateLeft, &[KeyCode::Q]);
+        map.bind(Action::RotateRight, &[KeyCode::E]);
+        map.bind(Action::ToggleIsometric, &[KeyCode::Tab]);
+        map.bind(Action::QuickSave, &[KeyCode::F5]);
+        map.bind(Action::QuickLoad, &[KeyCode::F9]);
+        map.bind(Action::Cancel, &[KeyCode::Escape]);
+
+        map
+    }
+}
+
+impl InputMap {
+    /// Replaces the keys bound to an action.
+    pub fn bind(&mut self, action: Action, keys: &[KeyCode]) {
+        self.bindings.insert(action, keys.to_vec());
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings
+            .get(&action)
+            .map(|keys| keys.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        self.keys(action).iter().any(|key| input.pressed(*key))
+    }
+
+    pub fn just_pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        self.keys(action).iter().any(|key| input.just_pressed(*key))
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::asset::{HandleId, LoadState};
+use bevy::prelude::{Plugin as BevyPlugin, *};
+use std::path::PathBuf;
+
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<AppState>()
+            .init_resource::<LoadingAssets>()
+            .add_event::<LoadingFinished>()
+            .add_startup_system(setup.system())
+            .add_system(loading_system.system());
+    }
+}
+
+/// The top level state of the app. Assets are loaded in the background while `Loading` and the
+/// world is only spawned once everything is ready.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AppState {
+    Loading,
+    InGame,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState::Loading
+    }
+}
+
+/// Sent once when every asset has loaded and the app moves to `AppState::InGame`.
+pub struct LoadingFinished;
+
+/// Assets that must finish loading before the game starts.
+#[derive(Default)]
+pub struct LoadingAssets {
+    assets: Vec<LoadingAsset>,
+}
+
+impl LoadingAssets {
+    /// Starts loading an asset in the background. Problems are shown on the loading screen rather
+    /// than returned, so the handle is always usable even if the asset never arrives.
+    pub fn load<T: Resource>(&mut self, asset_server: &AssetServer, path: &str) -> Handle<T> {
+        let path = PathBuf::from(path);
+
+        match asset_server.load::<T, _>(&path) {
+            Ok(handle) => {
+                self.assets.push(LoadingAsset {
+                    path,
+                    handle: Some(handle.id),
+                    status: Status::Loading,
+                    reported: false,
+                });
+
+                handle
+            }
+            Err(error) => {
+                self.assets.push(LoadingAsset {
+                    path,
+                    handle: None,
+                    status: Status::Failed(error.to_string()),
+                    reported: false,
+                });
+
+                Handle::default()
+            }
+        }
+    }
+}
+
+struct LoadingAsset {
+    path: PathBuf,
+    handle: Option<HandleId>,
+    status: Status,
+    /// Whether a failure has been added to the loading screen.
+    reported: bool,
+}
+
+enum Status {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+impl LoadingAsset {
+    fn update(&mut self, asset_server: &AssetServer) {
+        let handle = match (&self.status, self.handle) {
+            (Status::Loading, Some(handle)) => handle,
+            _ => return,
+        };
+
+        match asset_server.get_load_state(handle) {
+            Some(LoadState::Loaded(_)) => self.status = Status::Loaded,
+            // The asset server only records that a load failed, not why. Reading the file again to
+            // find out would stall the frame, so only the path is shown.
+            Some(LoadState::Failed(_)) => {
+                self.status = Status::Failed("failed to load".to_string())
+            }
+            _ => {}
+        }
+    }
+}
+
+struct LoadingScreen {
+    font: Handle<Font>,
+}
+
+struct LoadingText;
+
+struct LoadingProgressBar;
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // The font is only needed for the loading screen so a missing one leaves it without text
+    // rather than stopping the game from starting.
+    let font = asset_server
+        .load("assets/fonts/DejaVuSans.ttf")
+        .unwrap_or_default();
+
+    commands
+        .spawn(NodeComponents {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgb(0.08, 0.08, 0.1).into()),
+            ..Default::default()
+        })
+        .with(LoadingScreen { font })
+        .with_children(|parent| {
+            parent
+                .spawn(TextComponents {
+                    text: Text {
+                        value: "Loading".to_string(),
+                        font,
+                        style: TextStyle {
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(LoadingText);
+
+            parent
+                .spawn(NodeComponents {
+                    style: Style {
+                        size: Size::new(Val::Px(400.0), Val::Px(16.0)),
+                        margin: Rect::all(Val::Px(20.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgb(0.2, 0.2, 0.25).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeComponents {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..Default::default()
+                            },
+                            material: materials.add(Color::rgb(0.9, 0.9, 0.9).into()),
+                            ..Default::default()
+                        })
+                        .with(LoadingProgressBar);
+                });
+        });
+}
+
+fn loading_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<AppState>,
+    mut loading: ResMut<LoadingAssets>,
+    mut finished_events: ResMut<Events<LoadingFinished>>,
+    mut screens: Query<(Entity, &LoadingScreen)>,
+    mut texts: Query<With<LoadingText, &mut Text>>,
+    mut progress_bars: Query<With<LoadingProgressBar, &mut Style>>,
+) {
+    if *state != AppState::Loading {
+        return;
+    }
+
+    for asset in loading.assets.iter_mut() {
+        asset.update(&asset_server);
+    }
+
+    let total = loading.assets.len();
+    let loaded = loading
+        .assets
+        .iter()
+        .filter(|asset| matches!(asset.status, Status::Loaded))
+        .count();
+    let failed = loading
+        .assets
+        .iter()
+        .filter(|asset| matches!(asset.status, Status::Failed(_)))
+        .count();
+
+    if loaded == total {
+        for (entity, _) in &mut screens.iter() {
+            commands.despawn_recursive(entity);
+        }
+
+        loading.assets.clear();
+        *state = AppState::InGame;
+        finished_events.send(LoadingFinished);
+        return;
+    }
+
+    for mut style in &mut progress_bars.iter() {
+        style.size.width = Val::Percent(100.0 * loaded as f32 / total as f32);
+    }
+
+    for mut text in &mut texts.iter() {
+        text.value = if failed > 0 {
+            format!("Failed to load {} of {} assets", failed, total)
+        } else {
+            format!("Loading {} of {} assets", loaded + 1, total)
+        };
+    }
+
+    // Each failure gets its own line under the progress bar, naming the file and the error.
+    for (screen_entity, screen) in &mut screens.iter() {
+        for asset in loading.assets.iter_mut() {
+            let error = match &asset.status {
+                Status::Failed(error) if !asset.reported => error,
+                _ => continue,
+            };
+
+            let failure = commands
+                .spawn(TextComponents {
+                    text: Text {
+                        value: format!("{}: {}", asset.path.display(), error),
+                        font: screen.font,
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::rgb(1.0, 0.4, 0.4),
+                        },
+                    },
+                    ..Default::default()
+                })
+                .current_entity()
+                .unwrap();
+
+            commands.push_children(screen_entity, &[failure]);
+            asset.reported = true;
+        }
+    }
+}
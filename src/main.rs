@@ -1,79 +1,149 @@
 use bevy::prelude::*;
 use bevy_mod_picking::*;
+use zville_voxel as voxel;
 
 mod camera;
 mod cursor;
-mod voxel;
+mod input;
+mod loading;
+mod message;
+mod prefab;
+mod save;
 mod window;
 
 fn main() {
     App::build()
         .add_plugin(window::Plugin)
         .add_default_plugins()
-        .add_system(bevy::input::system::exit_on_esc_system.system())
+        .add_plugin(input::Plugin)
+        .add_plugin(loading::Plugin)
+        .add_plugin(message::Plugin)
         .add_plugin(cursor::Plugin)
         .add_plugin(voxel::Plugin)
+        .add_plugin(prefab::Plugin)
+        .add_plugin(save::Plugin)
         .add_plugin(camera::Plugin)
         .add_plugin(PickingPlugin)
         .add_startup_stage_after(camera::STARTUP_STAGE, "main")
         .add_startup_system_to_stage("main", setup.system())
+        .add_system(spawn_world_system.system())
+        .add_system(exit_system.system())
         .run();
 }
 
+/// The prefab placed in the world when the game starts.
+const PLACED_PREFAB: &str = "small_house";
+
+/// Handles to the assets the world is built from once loading has finished.
+struct WorldAssets {
+    ground: Handle<voxel::VoxelModel>,
+}
+
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    camera_pick_group: Res<camera::CameraPickingGroup>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut voxel_matrices: ResMut<Assets<voxel::Matrix>>,
+    mut loading: ResMut<loading::LoadingAssets>,
+    mut messages: ResMut<Events<message::Message>>,
 ) {
-    let ground_handle = asset_server
-        .load_sync(&mut voxel_matrices, "assets/ground.qb")
-        .unwrap();
-
-    let small_model_handle = asset_server
-        .load_sync(&mut voxel_matrices, "assets/16x16x16.qb")
-        .unwrap();
-
-    let ground = voxel_matrices.get(&ground_handle).unwrap();
-    let small_model = voxel_matrices.get(&small_model_handle).unwrap();
-
-    for (mesh, color) in ground.mesh_parts() {
-        let handle = meshes.add(mesh);
-        commands
-            .spawn(PbrComponents {
-                mesh: handle,
-                material: materials.add(StandardMaterial {
-                    albedo: color,
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .with(PickableMesh::new([camera_pick_group.0].into()));
+    // Voxel models are respawned when their files change so artists can see edits without a
+    // restart. The game still works without it, so a failure is only reported.
+    if let Err(error) = asset_server.watch_for_changes() {
+        messages.send(message::Message::error(format!(
+            "assets will not reload when changed: {}",
+            error
+        )));
     }
 
-    for (mesh, color) in small_model.mesh_parts() {
-        let handle = meshes.add(mesh);
-        commands
-            .spawn(PbrComponents {
-                transform: Transform::from_translation_rotation_scale(
-                    Vec3::new(10.0, 5.0, 10.0),
-                    Quat::identity(),
-                    1.0 / 16.0,
-                ),
-                mesh: handle,
-                material: materials.add(StandardMaterial {
-                    albedo: color,
-                    ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .with(PickableMesh::new([camera_pick_group.0].into()));
-    }
+    commands.insert_resource(WorldAssets {
+        ground: loading.load(&asset_server, "assets/ground.qb"),
+    });
 
     commands.spawn(LightComponents {
         transform: Transform::from_translation(Vec3::new(0.0, 250.0, 0.0)),
         ..Default::default()
     });
 }
+
+fn spawn_world_system(
+    mut commands: Commands,
+    mut event_reader: Local<EventReader<loading::LoadingFinished>>,
+    events: Res<Events<loading::LoadingFinished>>,
+    mut messages: ResMut<Events<message::Message>>,
+    asset_server: Res<AssetServer>,
+    world_assets: Res<WorldAssets>,
+    catalogs: Res<Assets<prefab::PrefabCatalog>>,
+    catalog: Res<prefab::PrefabCatalogHandle>,
+) {
+    if event_reader.latest(&events).is_none() {
+        return;
+    }
+
+    // The ground and the placed model are right handed Qubicle files, so loading mirrors them to
+    // span negative Z. The ground is moved back to cover (0, 0, 0) to (50, 5, 50).
+    commands
+        .spawn(voxel::VoxelModelComponents {
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 50.0)),
+            ..voxel::VoxelModelComponents::new(world_assets.ground)
+        })
+        .with(save::Terrain);
+
+    let prefab = match catalogs
+        .get(&catalog.0)
+        .and_then(|catalog| catalog.get(PLACED_PREFAB))
+    {
+        Some(prefab) => prefab,
+        None => {
+            messages.send(message::Message::error(format!(
+                "prefab {} is not in the catalog",
+                PLACED_PREFAB
+            )));
+            return;
+        }
+    };
+
+    // Prefab models are not known until the catalog has loaded, so the model loads in the
+    // background and its parts are spawned once it arrives.
+    let model = match asset_server.load(&prefab.model) {
+        Ok(model) => model,
+        Err(error) => {
+            messages.send(message::Message::error(format!(
+                "failed to load {}: {}",
+                prefab.model, error
+            )));
+            return;
+        }
+    };
+
+    // Prefab models are authored at 16 voxels per world unit. They are scaled on the entity rather
+    // than the asset so the scale survives a reload. The one tile prefab is placed so that it
+    // covers (10, 5, 10) to (11, 6, 11), sitting on the ground with its base centre at
+    // (10.5, 5.0, 10.5).
+    commands
+        .spawn(voxel::VoxelModelComponents {
+            transform: Transform::from_translation_rotation_scale(
+                Vec3::new(10.0, 5.0, 11.0),
+                Quat::identity(),
+                1.0 / 16.0,
+            ),
+            ..voxel::VoxelModelComponents::new(model)
+        })
+        .with(save::ModelPath(prefab.model.clone()))
+        .with(camera::Followable);
+}
+
+/// Cancel leaves follow mode first, so the app only quits when the camera is not following
+/// anything. Following stops at the end of the frame, so a single press never does both.
+fn exit_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<input::InputMap>,
+    mut app_exit_events: ResMut<Events<bevy::app::AppExit>>,
+    mut targets: Query<&camera::CameraTarget>,
+) {
+    if !input_map.just_pressed(input::Action::Cancel, &keyboard_input) {
+        return;
+    }
+
+    if targets.iter().iter().next().is_none() {
+        app_exit_events.send(bevy::app::AppExit);
+    }
+}
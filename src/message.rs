@@ -0,0 +1,125 @@
+use bevy::prelude::{Plugin as BevyPlugin, *};
+
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<Message>()
+            .add_startup_system(setup.system())
+            .add_system(message_system.system());
+    }
+}
+
+/// A line of text shown to the player for a few seconds, such as the result of saving the city.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub text: String,
+    pub is_error: bool,
+}
+
+impl Message {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: false,
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// How long a message stays on screen, in seconds.
+const MESSAGE_DURATION: f64 = 4.0;
+/// Older messages are dropped early once there are more than this on screen.
+const MAX_MESSAGES: usize = 5;
+
+/// The messages on screen, newest first, along with when each one goes away.
+#[derive(Default)]
+struct MessageLog {
+    messages: Vec<(Message, f64)>,
+}
+
+/// One of the lines of text that messages are shown in, counting up from the bottom of the
+/// screen. The lines are spawned once and reused, so the UI hierarchy never changes.
+struct MessageLine(usize);
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // As with the loading screen, a missing font leaves messages without text rather than stopping
+    // the game from starting.
+    let font = asset_server
+        .load("assets/fonts/DejaVuSans.ttf")
+        .unwrap_or_default();
+
+    commands
+        .spawn(NodeComponents {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::Column,
+                ..Default::default()
+            },
+            draw: Draw {
+                is_transparent: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for index in 0..MAX_MESSAGES {
+                parent
+                    .spawn(TextComponents {
+                        text: Text {
+                            value: String::new(),
+                            font,
+                            style: TextStyle {
+                                font_size: 20.0,
+                                color: Color::WHITE,
+                            },
+                        },
+                        ..Default::default()
+                    })
+                    .with(MessageLine(index));
+            }
+        });
+}
+
+fn message_system(
+    mut log: Local<MessageLog>,
+    mut event_reader: Local<EventReader<Message>>,
+    events: Res<Events<Message>>,
+    time: Res<Time>,
+    mut lines: Query<(&MessageLine, &mut Text)>,
+) {
+    let now = time.seconds_since_startup;
+
+    for message in event_reader.iter(&events) {
+        log.messages
+            .insert(0, (message.clone(), now + MESSAGE_DURATION));
+    }
+
+    log.messages.retain(|(_, expires_at)| *expires_at > now);
+    log.messages.truncate(MAX_MESSAGES);
+
+    for (line, mut text) in &mut lines.iter() {
+        match log.messages.get(line.0) {
+            Some((message, _)) => {
+                text.value = message.text.clone();
+                text.style.color = if message.is_error {
+                    Color::rgb(1.0, 0.4, 0.4)
+                } else {
+                    Color::WHITE
+                };
+            }
+            None => text.value.clear(),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::loading::LoadingAssets;
+use anyhow::Context;
+use bevy::asset::AssetLoader;
+use bevy::prelude::{Plugin as BevyPlugin, *};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The catalog of placeable objects that build tools and menus are populated from.
+pub const CATALOG_PATH: &str = "assets/prefabs.ron";
+
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<PrefabCatalog>()
+            .add_asset_loader::<PrefabCatalog, PrefabCatalogLoader>()
+            .init_resource::<PrefabCatalog>()
+            .add_startup_system(setup.system())
+            .add_system(update_prefab_catalog_system.system());
+    }
+}
+
+/// Every object the player can place, as read from `CATALOG_PATH`. The resource is replaced
+/// whenever the file is reloaded so designers can tweak prefabs while the game is running.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PrefabCatalog {
+    pub prefabs: Vec<Prefab>,
+}
+
+impl PrefabCatalog {
+    pub fn from_ron(bytes: &[u8]) -> anyhow::Result<Self> {
+        let catalog: Self = ron::de::from_bytes(bytes)?;
+        catalog.validate()?;
+        Ok(catalog)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Prefab> {
+        self.prefabs.iter().find(|prefab| prefab.id == id)
+    }
+
+    /// The prefabs shown under a menu category, in the order they appear in the catalog.
+    pub fn in_category<'a>(&'a self, category: &'a str) -> impl Iterator<Item = &'a Prefab> + 'a {
+        self.prefabs
+            .iter()
+            .filter(move |prefab| prefab.category == category)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut ids = HashSet::new();
+
+        for prefab in self.prefabs.iter() {
+            if !ids.insert(prefab.id.as_str()) {
+                return Err(anyhow::anyhow!("prefab {} is defined twice", prefab.id));
+            }
+
+            prefab
+                .validate()
+                .with_context(|| format!("invalid prefab {}", prefab.id))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A placeable object such as a building or a piece of road.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prefab {
+    /// Unique name used to refer to the prefab from code and save files.
+    pub id: String,
+    /// Name shown to the player.
+    pub name: String,
+    /// Path of the voxel model, relative to the working directory like other asset paths.
+    pub model: String,
+    pub footprint: Footprint,
+    /// Rotations in degrees around the Y axis that the prefab may be placed at.
+    #[serde(default = "all_rotations")]
+    pub rotations: Vec<u16>,
+    pub cost: u32,
+    /// Groups prefabs in the build menu.
+    pub category: String,
+    /// The zone the prefab grows in, for buildings that are not placed directly by the player.
+    #[serde(default)]
+    pub zone: Option<ZoneType>,
+}
+
+impl Prefab {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.footprint.width == 0 || self.footprint.depth == 0 {
+            return Err(anyhow::anyhow!("footprint must be at least one tile"));
+        }
+
+        if self.rotations.is_empty() {
+            return Err(anyhow::anyhow!("at least one rotation must be allowed"));
+        }
+
+        if let Some(rotation) = self.rotations.iter().find(|rotation| **rotation % 90 != 0) {
+            return Err(anyhow::anyhow!(
+                "rotation {} is not a multiple of 90 degrees",
+                rotation
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn all_rotations() -> Vec<u16> {
+    vec![0, 90, 180, 270]
+}
+
+/// The area a prefab covers, in tiles, before it is rotated.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub struct Footprint {
+    pub width: u32,
+    pub depth: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+pub enum ZoneType {
+    Residential,
+    Commercial,
+    Industrial,
+}
+
+#[derive(Default)]
+pub struct PrefabCatalogLoader;
+
+impl AssetLoader<PrefabCatalog> for PrefabCatalogLoader {
+    fn from_bytes(
+        &self,
+        _path: &Path,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<PrefabCatalog, anyhow::Error> {
+        PrefabCatalog::from_ron(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["ron"];
+        EXTENSIONS
+    }
+}
+
+/// The catalog asset behind the `PrefabCatalog` resource. The resource is only updated once the
+/// asset's created event arrives, which can be a frame after loading finishes, so code that runs
+/// as soon as the game starts reads the asset through this instead.
+pub struct PrefabCatalogHandle(pub Handle<PrefabCatalog>);
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingAssets>,
+) {
+    let handle = loading.load(&asset_server, CATALOG_PATH);
+    commands.insert_resource(PrefabCatalogHandle(handle));
+}
+
+fn update_prefab_catalog_system(
+    mut event_reader: Local<EventReader<AssetEvent<PrefabCatalog>>>,
+    events: Res<Events<AssetEvent<PrefabCatalog>>>,
+    catalogs: Res<Assets<PrefabCatalog>>,
+    handle: Res<PrefabCatalogHandle>,
+    mut catalog: ResMut<PrefabCatalog>,
+) {
+    for event in event_reader.iter(&events) {
+        match event {
+            AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed }
+                if *changed == handle.0 =>
+            {
+                if let Some(loaded) = catalogs.get(&handle.0) {
+                    *catalog = loaded.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog(prefabs: &[&str]) -> String {
+        format!("(prefabs: [{}])", prefabs.join(", "))
+    }
+
+    /// A prefab with every required field but its footprint, followed by `extra` fields.
+    fn prefab(id: &str, extra: &str) -> String {
+        format!(
+            r#"(id: "{}", name: "Test", model: "assets/test.qb", cost: 1, category: "Test", {})"#,
+            id, extra
+        )
+    }
+
+    fn one_tile(id: &str) -> String {
+        prefab(id, "footprint: (width: 1, depth: 1)")
+    }
+
+    #[test]
+    fn reads_the_shipped_catalog() {
+        let catalog = PrefabCatalog::from_ron(include_bytes!("../assets/prefabs.ron")).unwrap();
+
+        let small_house = catalog.get("small_house").unwrap();
+        assert_eq!(small_house.zone, Some(ZoneType::Residential));
+        assert_eq!(catalog.get("kiosk").unwrap().rotations, vec![0, 180]);
+        assert_eq!(catalog.in_category("Commercial").count(), 1);
+    }
+
+    #[test]
+    fn allows_every_rotation_by_default() {
+        let catalog = PrefabCatalog::from_ron(catalog(&[&one_tile("house")]).as_bytes()).unwrap();
+        let house = catalog.get("house").unwrap();
+
+        assert_eq!(house.rotations, vec![0, 90, 180, 270]);
+        assert_eq!(house.footprint, Footprint { width: 1, depth: 1 });
+        assert_eq!(house.zone, None);
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let ron = catalog(&[&one_tile("house"), &one_tile("shop"), &one_tile("house")]);
+        let error = PrefabCatalog::from_ron(ron.as_bytes()).unwrap_err();
+
+        assert_eq!(error.to_string(), "prefab house is defined twice");
+    }
+
+    #[test]
+    fn rejects_empty_footprints() {
+        for footprint in ["(width: 0, depth: 1)", "(width: 2, depth: 0)"].iter() {
+            let ron = catalog(&[&prefab("house", &format!("footprint: {}", footprint))]);
+            let error = PrefabCatalog::from_ron(ron.as_bytes()).unwrap_err();
+
+            assert_eq!(
+                format!("{:#}", error),
+                "invalid prefab house: footprint must be at least one tile"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_rotations_that_are_not_multiples_of_90_degrees() {
+        let ron = catalog(&[&prefab(
+            "house",
+            "footprint: (width: 1, depth: 1), rotations: [0, 45]",
+        )]);
+        let error = PrefabCatalog::from_ron(ron.as_bytes()).unwrap_err();
+
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid prefab house: rotation 45 is not a multiple of 90 degrees"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_rotations() {
+        let ron = catalog(&[&prefab(
+            "house",
+            "footprint: (width: 1, depth: 1), rotations: []",
+        )]);
+
+        assert!(PrefabCatalog::from_ron(ron.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        let ron = catalog(&[&prefab("house", "rotations: [0]")]);
+
+        assert!(PrefabCatalog::from_ron(ron.as_bytes()).is_err());
+    }
+}
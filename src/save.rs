@@ -0,0 +1,661 @@
+//! Saving and loading cities. A save holds the terrain voxels and the path and transform of each
+//! placed model, which is all of the city that can change in game so far. The camera rig and the
+//! model picked for it to follow are deliberately left out as they are view state rather than part
+//! of the city: loading a save keeps the current view and stops following the despawned model.
+//! The format is split into tagged sections so state such as the simulation can be added later.
+
+use crate::camera::Followable;
+use crate::input::{Action, InputMap};
+use crate::loading::AppState;
+use crate::message::Message;
+use anyhow::Context;
+use bevy::prelude::{Plugin as BevyPlugin, *};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use zville_voxel::{
+    chunk, ByteReader, Matrix, ReadError, ReadErrorKind, VoxelMesh, VoxelModel,
+    VoxelModelComponents, VoxelModelPart,
+};
+
+/// Where the quicksave and quickload keys, F5 and F9 by default, save to and load from.
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.zvs";
+
+pub struct Plugin;
+
+impl BevyPlugin for Plugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<SaveCity>()
+            .add_event::<LoadCity>()
+            .add_system(quicksave_system.system())
+            .add_system(save_city_system.system())
+            .add_system(load_city_system.system());
+    }
+}
+
+/// Asks for the city to be written to a file.
+pub struct SaveCity {
+    pub path: PathBuf,
+}
+
+/// Asks for the current city to be replaced by one read from a file.
+pub struct LoadCity {
+    pub path: PathBuf,
+}
+
+/// Marks the entity holding the voxel terrain of the city. Its voxels are stored in the save
+/// itself, chunk by chunk, as the terrain is expected to be edited in game.
+pub struct Terrain;
+
+/// Marks terrain whose model was built from a save with `Assets::add` rather than loaded by the
+/// asset server. Nothing else holds these models, so loading another save removes them.
+pub struct SavedTerrain;
+
+/// Marks a voxel model placed in the city along with the file it was loaded from. Only the path
+/// and transform are saved, the model is loaded from the file again.
+pub struct ModelPath(pub String);
+
+const MAGIC: &[u8; 4] = b"ZVCS";
+const VERSION: u32 = 1;
+
+/// Upgrades a save to the version after it, so `MIGRATIONS[0]` turns a version 1 save into
+/// version 2. Changing the format means bumping `VERSION` and adding a function here that
+/// rewrites the sections of the previous version.
+const MIGRATIONS: &[Migration] = &[];
+
+type Migration = fn(&mut Vec<Section>) -> anyhow::Result<()>;
+
+const TERRAIN_SECTION: [u8; 4] = *b"TERR";
+const MODELS_SECTION: [u8; 4] = *b"MODL";
+
+const MAX_SECTIONS: usize = 1024;
+const MAX_SECTION_LEN: usize = 1024 * 1024 * 1024;
+const MAX_CHUNKS: usize = 1024 * 1024;
+const MAX_MODELS: usize = 1024 * 1024;
+const MAX_PATH_LEN: usize = 4096;
+
+/// A tagged block of the save file. Sections a reader does not know about are skipped, which
+/// leaves room to add state such as the simulation without breaking older builds.
+pub struct Section {
+    pub tag: [u8; 4],
+    pub bytes: Vec<u8>,
+}
+
+/// Everything stored in a save file.
+#[derive(Debug, PartialEq)]
+pub struct CitySave {
+    pub terrain: Option<TerrainSave>,
+    pub models: Vec<ModelSave>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TerrainSave {
+    pub transform: Mat4,
+    pub pivot: Vec3,
+    pub voxel_size: f32,
+    /// Chunks of at most `chunk::CHUNK_SIZE` along with their offset in voxels.
+    pub chunks: Vec<(Vec3, Matrix)>,
+}
+
+impl TerrainSave {
+    pub fn from_model(model: &VoxelModel, transform: Mat4) -> Self {
+        let mut chunks = Vec::new();
+
+        for (_, matrix, offset) in model.matrices() {
+            for (chunk_offset, chunk) in chunk::split(matrix) {
+                chunks.push((offset + chunk_offset, chunk));
+            }
+        }
+
+        Self {
+            transform,
+            pivot: model.pivot,
+            voxel_size: model.voxel_size,
+            chunks,
+        }
+    }
+
+    /// Builds a model with a part for each chunk, so each chunk is meshed on its own.
+    pub fn into_model(self) -> VoxelModel {
+        let mut model = VoxelModel::new("terrain".to_string());
+        model.pivot = self.pivot;
+        model.voxel_size = self.voxel_size;
+
+        for (offset, matrix) in self.chunks {
+            let name = format!("chunk {} {} {}", offset.x(), offset.y(), offset.z());
+            model.parts.push(VoxelModelPart::new(name, matrix, offset));
+        }
+
+        model
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ModelSave {
+    pub path: String,
+    pub transform: Mat4,
+}
+
+pub fn write<W: Write>(writer: &mut W, save: &CitySave) -> io::Result<()> {
+    let mut sections = Vec::new();
+
+    if let Some(terrain) = &save.terrain {
+        let mut bytes = Vec::new();
+        write_mat4(&mut bytes, &terrain.transform)?;
+        write_vec3(&mut bytes, terrain.pivot)?;
+        bytes.write_f32::<LittleEndian>(terrain.voxel_size)?;
+
+        bytes.write_u32::<LittleEndian>(terrain.chunks.len() as u32)?;
+        for (offset, matrix) in terrain.chunks.iter() {
+            bytes.write_i32::<LittleEndian>(offset.x() as i32)?;
+            bytes.write_i32::<LittleEndian>(offset.y() as i32)?;
+            bytes.write_i32::<LittleEndian>(offset.z() as i32)?;
+            chunk::write(&mut bytes, matrix)?;
+        }
+
+        sections.push(Section {
+            tag: TERRAIN_SECTION,
+            bytes,
+        });
+    }
+
+    let mut bytes = Vec::new();
+    bytes.write_u32::<LittleEndian>(save.models.len() as u32)?;
+    for model in save.models.iter() {
+        bytes.write_u32::<LittleEndian>(model.path.len() as u32)?;
+        bytes.write_all(model.path.as_bytes())?;
+        write_mat4(&mut bytes, &model.transform)?;
+    }
+
+    sections.push(Section {
+        tag: MODELS_SECTION,
+        bytes,
+    });
+
+    writer.write_all(MAGIC)?;
+    writer.write_u32::<LittleEndian>(VERSION)?;
+    writer.write_u32::<LittleEndian>(sections.len() as u32)?;
+
+    for section in sections.iter() {
+        writer.write_all(&section.tag)?;
+        writer.write_u32::<LittleEndian>(section.bytes.len() as u32)?;
+        writer.write_all(&section.bytes)?;
+    }
+
+    Ok(())
+}
+
+pub fn read(bytes: &[u8]) -> anyhow::Result<CitySave> {
+    let mut reader = ByteReader::new(bytes);
+
+    if reader.read("magic", 4)? != MAGIC {
+        return Err(anyhow::anyhow!("not a zVille save file"));
+    }
+
+    let version = reader.read_u32("version")?;
+    if version == 0 || version > VERSION {
+        return Err(anyhow::anyhow!(
+            "save version {} is not supported, the newest supported version is {}",
+            version,
+            VERSION
+        ));
+    }
+
+    let section_count = reader.read_len("section count", MAX_SECTIONS)?;
+    let mut sections = Vec::with_capacity(section_count);
+
+    for _ in 0..section_count {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(reader.read("section tag", 4)?);
+        let len = reader.read_len("section length", MAX_SECTION_LEN)?;
+        let bytes = reader.read("section", len)?.to_vec();
+
+        sections.push(Section { tag, bytes });
+    }
+
+    migrate(version, MIGRATIONS, &mut sections)?;
+
+    let mut save = CitySave {
+        terrain: None,
+        models: Vec::new(),
+    };
+
+    for section in sections.iter() {
+        let mut reader = ByteReader::new(&section.bytes);
+
+        match section.tag {
+            TERRAIN_SECTION => save.terrain = Some(read_terrain(&mut reader)?),
+            MODELS_SECTION => save.models = read_models(&mut reader)?,
+            _ => {}
+        }
+    }
+
+    Ok(save)
+}
+
+/// Upgrades the sections of a save of `version` by running each migration after it in turn.
+fn migrate(
+    version: u32,
+    migrations: &[Migration],
+    sections: &mut Vec<Section>,
+) -> anyhow::Result<()> {
+    for (index, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        let from = index + 1;
+
+        migration(sections)
+            .with_context(|| format!("failed to migrate save version {} to {}", from, from + 1))?;
+    }
+
+    Ok(())
+}
+
+fn read_terrain(reader: &mut ByteReader) -> Result<TerrainSave, ReadError> {
+    let transform = read_mat4(reader, "terrain transform")?;
+    let pivot = read_vec3(reader, "terrain pivot")?;
+    let voxel_size = reader.read_f32("terrain voxel size")?;
+
+    let chunk_count = reader.read_len("chunk count", MAX_CHUNKS)?;
+    let mut chunks = Vec::with_capacity(chunk_count);
+
+    for _ in 0..chunk_count {
+        let offset = Vec3::new(
+            reader.read_i32("chunk offset")? as f32,
+            reader.read_i32("chunk offset")? as f32,
+            reader.read_i32("chunk offset")? as f32,
+        );
+
+        chunks.push((offset, chunk::read(reader)?));
+    }
+
+    Ok(TerrainSave {
+        transform,
+        pivot,
+        voxel_size,
+        chunks,
+    })
+}
+
+fn read_models(reader: &mut ByteReader) -> Result<Vec<ModelSave>, ReadError> {
+    let count = reader.read_len("model count", MAX_MODELS)?;
+    let mut models = Vec::with_capacity(count.min(1024));
+
+    for _ in 0..count {
+        let len = reader.read_len("model path length", MAX_PATH_LEN)?;
+        let path = reader.read_string("model path", len)?;
+        let transform = read_mat4(reader, "model transform")?;
+
+        models.push(ModelSave { path, transform });
+    }
+
+    Ok(models)
+}
+
+fn write_mat4<W: Write>(writer: &mut W, value: &Mat4) -> io::Result<()> {
+    for value in value.to_cols_array().iter() {
+        writer.write_f32::<LittleEndian>(*value)?;
+    }
+
+    Ok(())
+}
+
+fn read_mat4(reader: &mut ByteReader, field: &'static str) -> Result<Mat4, ReadError> {
+    let mut values = [0.0; 16];
+
+    for value in values.iter_mut() {
+        *value = reader.read_f32(field)?;
+    }
+
+    let offset = reader.offset();
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(ReadError::new(
+            field,
+            offset,
+            ReadErrorKind::Invalid("transform is not finite".to_string()),
+        ));
+    }
+
+    Ok(Mat4::from_cols_array(&values))
+}
+
+fn write_vec3<W: Write>(writer: &mut W, value: Vec3) -> io::Result<()> {
+    writer.write_f32::<LittleEndian>(value.x())?;
+    writer.write_f32::<LittleEndian>(value.y())?;
+    writer.write_f32::<LittleEndian>(value.z())
+}
+
+fn read_vec3(reader: &mut ByteReader, field: &'static str) -> Result<Vec3, ReadError> {
+    Ok(Vec3::new(
+        reader.read_f32(field)?,
+        reader.read_f32(field)?,
+        reader.read_f32(field)?,
+    ))
+}
+
+fn quicksave_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_map: Res<InputMap>,
+    mut save_events: ResMut<Events<SaveCity>>,
+    mut load_events: ResMut<Events<LoadCity>>,
+) {
+    if input_map.just_pressed(Action::QuickSave, &keyboard_input) {
+        save_events.send(SaveCity {
+            path: PathBuf::from(QUICKSAVE_PATH),
+        });
+    }
+
+    if input_map.just_pressed(Action::QuickLoad, &keyboard_input) {
+        load_events.send(LoadCity {
+            path: PathBuf::from(QUICKSAVE_PATH),
+        });
+    }
+}
+
+fn save_city_system(
+    mut event_reader: Local<EventReader<SaveCity>>,
+    events: Res<Events<SaveCity>>,
+    mut messages: ResMut<Events<Message>>,
+    state: Res<AppState>,
+    models: Res<Assets<VoxelModel>>,
+    mut terrain: Query<With<Terrain, (&Handle<VoxelModel>, &Transform)>>,
+    mut placed: Query<(&ModelPath, &Transform)>,
+) {
+    for event in event_reader.iter(&events) {
+        if *state != AppState::InGame {
+            messages.send(Message::error(format!(
+                "cannot save {} while loading",
+                event.path.display()
+            )));
+            continue;
+        }
+
+        let mut save = CitySave {
+            terrain: None,
+            models: Vec::new(),
+        };
+
+        for (handle, transform) in &mut terrain.iter() {
+            if let Some(model) = models.get(&handle) {
+                save.terrain = Some(TerrainSave::from_model(model, transform.value));
+            }
+        }
+
+        for (path, transform) in &mut placed.iter() {
+            save.models.push(ModelSave {
+                path: path.0.clone(),
+                transform: transform.value,
+            });
+        }
+
+        messages.send(match write_file(&event.path, &save) {
+            Ok(()) => Message::info(format!("saved city to {}", event.path.display())),
+            Err(error) => Message::error(format!(
+                "failed to save {}: {:#}",
+                event.path.display(),
+                error
+            )),
+        });
+    }
+}
+
+fn write_file(path: &Path, save: &CitySave) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer, save)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn load_city_system(
+    mut commands: Commands,
+    mut event_reader: Local<EventReader<LoadCity>>,
+    events: Res<Events<LoadCity>>,
+    mut messages: ResMut<Events<Message>>,
+    state: Res<AppState>,
+    asset_server: Res<AssetServer>,
+    mut models: ResMut<Assets<VoxelModel>>,
+    (mut meshes, mut materials): (ResMut<Assets<Mesh>>, ResMut<Assets<StandardMaterial>>),
+    mut terrain: Query<With<Terrain, Entity>>,
+    mut saved_terrain: Query<With<SavedTerrain, &Handle<VoxelModel>>>,
+    mut placed: Query<With<ModelPath, Entity>>,
+    mut voxel_meshes: Query<(&VoxelMesh, &Handle<Mesh>, &Handle<StandardMaterial>)>,
+) {
+    for event in event_reader.iter(&events) {
+        if *state != AppState::InGame {
+            messages.send(Message::error(format!(
+                "cannot load {} while loading",
+                event.path.display()
+            )));
+            continue;
+        }
+
+        // The current city is only torn down once the save has been read successfully.
+        let save = match fs::read(&event.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| read(&bytes))
+        {
+            Ok(save) => save,
+            Err(error) => {
+                messages.send(Message::error(format!(
+                    "failed to load {}: {:#}",
+                    event.path.display(),
+                    error
+                )));
+                continue;
+            }
+        };
+
+        let mut despawned = HashSet::new();
+
+        // Models built from a previous save are only used by their terrain, so they go with it.
+        // Terrain loaded through the asset server is left for the server to free once its handles
+        // are dropped.
+        for handle in &mut saved_terrain.iter() {
+            models.remove(&handle);
+        }
+
+        for entity in &mut terrain.iter() {
+            commands.despawn_recursive(entity);
+            despawned.insert(entity);
+        }
+
+        for entity in &mut placed.iter() {
+            commands.despawn_recursive(entity);
+            despawned.insert(entity);
+        }
+
+        // Each mesh and material belongs to a single part, so they go with the models.
+        for (voxel_mesh, mesh, material) in &mut voxel_meshes.iter() {
+            if despawned.contains(&voxel_mesh.model) {
+                meshes.remove(mesh);
+                materials.remove(material);
+            }
+        }
+
+        if let Some(terrain) = save.terrain {
+            let transform = Transform::new(terrain.transform);
+            let handle = models.add(terrain.into_model());
+
+            commands
+                .spawn(VoxelModelComponents {
+                    transform,
+                    ..VoxelModelComponents::new(handle)
+                })
+                .with(Terrain)
+                .with(SavedTerrain);
+        }
+
+        for model in save.models {
+            let handle = match asset_server.load(&model.path) {
+                Ok(handle) => handle,
+                Err(error) => {
+                    messages.send(Message::error(format!(
+                        "failed to load {}: {}",
+                        model.path, error
+                    )));
+                    continue;
+                }
+            };
+
+            commands
+                .spawn(VoxelModelComponents {
+                    transform: Transform::new(model.transform),
+                    ..VoxelModelComponents::new(handle)
+                })
+                .with(ModelPath(model.path))
+                .with(Followable);
+        }
+
+        messages.send(Message::info(format!(
+            "loaded city from {}",
+            event.path.display()
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zville_voxel::Voxel;
+
+    fn fixture() -> CitySave {
+        let mut ground = Matrix::new(40, 2, 3);
+        for z in 0..3 {
+            for x in 0..40 {
+                ground.set(
+                    Vec3::new(x as f32, 0.0, z as f32),
+                    Voxel::Solid(Color::rgb_u8(0, 128, 0)),
+                );
+            }
+        }
+
+        let mut model = VoxelModel::new("terrain".to_string());
+        model.pivot = Vec3::new(20.0, 0.0, 1.5);
+        model.voxel_size = 0.5;
+        model.parts.push(VoxelModelPart::new(
+            "ground".to_string(),
+            ground,
+            Vec3::new(-8.0, 0.0, 4.0),
+        ));
+
+        CitySave {
+            terrain: Some(TerrainSave::from_model(
+                &model,
+                Mat4::from_translation(Vec3::new(0.0, 0.0, 50.0)),
+            )),
+            models: vec![ModelSave {
+                path: "assets/models/16x16x16.qb".to_string(),
+                transform: Mat4::from_scale_rotation_translation(
+                    Vec3::new(0.25, 0.5, 2.0),
+                    Quat::from_rotation_y(0.75),
+                    Vec3::new(10.0, 5.0, -11.0),
+                ),
+            }],
+        }
+    }
+
+    fn write_to_vec(save: &CitySave) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, save).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips_a_city() {
+        let save = fixture();
+        let terrain = save.terrain.as_ref().unwrap();
+        assert_eq!(terrain.chunks.len(), 2);
+
+        assert_eq!(read(&write_to_vec(&save)).unwrap(), save);
+    }
+
+    #[test]
+    fn round_trips_a_city_without_terrain() {
+        let save = CitySave {
+            terrain: None,
+            models: Vec::new(),
+        };
+
+        assert_eq!(read(&write_to_vec(&save)).unwrap(), save);
+    }
+
+    #[test]
+    fn skips_unknown_sections() {
+        let mut bytes = write_to_vec(&fixture());
+
+        // Bumps the section count and appends a section this version knows nothing about.
+        let section_count = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        bytes[8..12].copy_from_slice(&(section_count + 1).to_le_bytes());
+        bytes.extend_from_slice(b"SIMS");
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        assert_eq!(read(&bytes).unwrap(), fixture());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut bytes = write_to_vec(&fixture());
+
+        for version in [0, VERSION + 1].iter() {
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+
+            assert!(read(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut bytes = write_to_vec(&fixture());
+        bytes[0..4].copy_from_slice(b"RIFF");
+
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn migrations_reach_the_current_version() {
+        assert_eq!(MIGRATIONS.len() + 1, VERSION as usize);
+    }
+
+    fn add_section(sections: &mut Vec<Section>) -> anyhow::Result<()> {
+        sections.push(Section {
+            tag: *b"NEW ",
+            bytes: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    fn fail(_: &mut Vec<Section>) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("section is malformed"))
+    }
+
+    #[test]
+    fn runs_migrations_from_the_save_version() {
+        let migrations: &[Migration] = &[add_section, add_section, add_section];
+
+        let mut sections = Vec::new();
+        migrate(2, migrations, &mut sections).unwrap();
+        assert_eq!(sections.len(), 2);
+
+        let mut sections = Vec::new();
+        migrate(4, migrations, &mut sections).unwrap();
+        assert!(sections.is_empty());
+    }
+
+    #[test]
+    fn reports_failed_migrations() {
+        let migrations: &[Migration] = &[add_section, fail, add_section];
+        let mut sections = Vec::new();
+
+        let error = migrate(1, migrations, &mut sections).unwrap_err();
+
+        assert_eq!(error.to_string(), "failed to migrate save version 2 to 3");
+        assert_eq!(sections.len(), 1);
+    }
+}